@@ -1,10 +1,202 @@
-use std::time::Instant;
-use wave_function_collapse::constraint_solver::SolverState;
+use std::time::{Duration, Instant};
+use wave_function_collapse::constraint_solver::{Grid, Solution, SolveError, SolverMove, SolveLimits, SolveOutcome, SolverConfig, SolverState, PUZZLE_HEIGHT, PUZZLE_WIDTH};
+use wave_function_collapse::exact_cover::ExactCoverSolver;
+use wave_function_collapse::observer::{Rejection, SolverObserver};
+use wave_function_collapse::parallel::ParallelSolver;
+use wave_function_collapse::heuristics::{cell_selector_by_name, piece_orderer_by_name};
+use wave_function_collapse::piece::get_piece_domain;
+use wave_function_collapse::piece_set::load_piece_set;
+use wave_function_collapse::restarts::RestartSchedule;
+use wave_function_collapse::sat::encode_puzzle;
+use wave_function_collapse::symmetry::solved_cells;
+use wave_function_collapse::trace::TraceRecorder;
 
 fn main() {
-    let mut solver = SolverState::new();
+    // Usage: time_solver [--count | --unique [--reflections]] [--break-symmetry] [--cells=<selector>] [--pieces=<orderer>] [--seed=<n>] [--randomize] [--restarts=luby|geometric] [--threads=<n>] [--max-steps=<n>] [--timeout-ms=<n>] [--log-events] [--trace=<path>] [--exact-cover] [--dimacs=<path> | --sat-model=<path>] [width height [piece_set]]
+    let (flags, args): (Vec<String>, Vec<String>) = std::env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let has_flag = |name: &str| flags.iter().any(|flag| flag == name);
+    let parse_dimension = |arg: &String| arg.parse::<usize>().expect("Board dimensions must be positive integers");
+    let (width, height) = match args.as_slice() {
+        [] => (PUZZLE_WIDTH, PUZZLE_HEIGHT),
+        [width, height] | [width, height, _] => (parse_dimension(width), parse_dimension(height)),
+        _ => panic!("Usage: time_solver [--count | --unique [--reflections]] [--break-symmetry] [--cells=<selector>] [--pieces=<orderer>] [--seed=<n>] [--randomize] [--restarts=luby|geometric] [--threads=<n>] [--max-steps=<n>] [--timeout-ms=<n>] [--log-events] [--trace=<path>] [--exact-cover] [--dimacs=<path> | --sat-model=<path>] [width height [piece_set]]"),
+    };
+    let pieces = match args.get(2) {
+        Some(path) => load_piece_set(path).unwrap_or_else(|err| panic!("Failed to load piece set {}: {}", path, err)),
+        None => get_piece_domain().clone(),
+    };
+
+    if let Some(path) = flags.iter().find_map(|flag| flag.strip_prefix("--dimacs=")) {
+        let encoding = encode_puzzle(width, height, &pieces);
+        std::fs::write(path, encoding.to_dimacs()).unwrap_or_else(|err| panic!("Failed to write {}: {}", path, err));
+        println!("Wrote {} variables and {} clauses to {}", encoding.variable_count, encoding.clauses.len(), path);
+        // Cross-check the clauses with a solution the search found
+        match SolverState::with_pieces(width, height, &pieces).solve() {
+            Ok(solution) => match encoding.model_for(&solution).and_then(|model| encoding.decode_model(&model)) {
+                Ok(decoded) if solved_cells(&decoded.grid) == solved_cells(&solution.grid) => println!("The search's solution satisfies every clause and decodes to the same grid"),
+                Ok(_) => println!("The search's solution decodes to a different grid"),
+                Err(err) => println!("The search's solution doesn't round trip through the clauses: {}", err),
+            },
+            Err(err) => println!("No solution to cross-check the clauses with: {}", err),
+        }
+        return;
+    }
+    if let Some(path) = flags.iter().find_map(|flag| flag.strip_prefix("--sat-model=")) {
+        let model = std::fs::read_to_string(path).unwrap_or_else(|err| panic!("Failed to read {}: {}", path, err));
+        match encode_puzzle(width, height, &pieces).decode_model(&model) {
+            Ok(solution) => {
+                println!("Model in {} solves the puzzle", path);
+                for placement in solution.placements {
+                    println!("  piece {:>2} {:?} at ({}, {})", placement.piece_id, placement.orientation, placement.x, placement.y);
+                }
+            }
+            Err(err) => println!("Model in {} doesn't solve the puzzle: {}", path, err),
+        }
+        return;
+    }
+
+    if has_flag("--exact-cover") {
+        let mut solver = ExactCoverSolver::with_pieces(width, height, &pieces);
+        let start = Instant::now();
+        if has_flag("--unique") {
+            let counts = solver.count_unique_solutions(has_flag("--reflections"));
+            println!("Enumeration time: {:?} ({} solutions, {} unique)", start.elapsed(), counts.raw, counts.unique);
+        } else if has_flag("--count") {
+            let solution_count = solver.count_solutions();
+            println!("Enumeration time: {:?} ({} solutions)", start.elapsed(), solution_count);
+        } else {
+            print_solve_result(solver.solve(), start.elapsed(), None);
+        }
+        return;
+    }
+
+    let mut config = SolverConfig::default();
+    if let Some(seed) = flags.iter().find_map(|flag| flag.strip_prefix("--seed=")) {
+        config = config.with_seed(seed.parse().expect("The seed must be a non-negative integer"));
+    }
+    if has_flag("--randomize") {
+        config = config.randomized();
+    }
+    if let Some(threads) = flags.iter().find_map(|flag| flag.strip_prefix("--threads=")) {
+        let mut solver = ParallelSolver::new(width, height, &pieces).with_config(config.clone()).with_threads(threads.parse().expect("The thread count must be a positive integer"));
+        if has_flag("--break-symmetry") {
+            solver = solver.with_broken_symmetry();
+        }
+        let start = Instant::now();
+        if has_flag("--unique") {
+            let counts = solver.count_unique_solutions(has_flag("--reflections"));
+            println!("Enumeration time: {:?} ({} solutions, {} unique, seed {})", start.elapsed(), counts.raw, counts.unique, config.seed);
+        } else if has_flag("--count") {
+            let solution_count = solver.count_solutions();
+            println!("Enumeration time: {:?} ({} solutions, seed {})", start.elapsed(), solution_count, config.seed);
+        } else {
+            print_solve_result(solver.solve(), start.elapsed(), Some(config.seed));
+        }
+        return;
+    }
+
+    let mut solver = SolverState::with_config(width, height, &pieces, config);
+    if has_flag("--break-symmetry") {
+        solver.break_symmetry();
+    }
+    if let Some(name) = flags.iter().find_map(|flag| flag.strip_prefix("--cells=")) {
+        solver.set_cell_selector(cell_selector_by_name(name).unwrap_or_else(|| panic!("Unknown cell selector {}, expected mrv, entropy, most-constrained, scan, random-mrv or random-entropy", name)));
+    }
+    if let Some(name) = flags.iter().find_map(|flag| flag.strip_prefix("--pieces=")) {
+        solver.set_piece_orderer(piece_orderer_by_name(name).unwrap_or_else(|| panic!("Unknown piece orderer {}, expected domain, largest, fewest-placements, random or least-constraining", name)));
+    }
+    if has_flag("--log-events") {
+        solver.add_observer(Box::new(EventLogger));
+    }
+    let trace_path = flags.iter().find_map(|flag| flag.strip_prefix("--trace="));
+    let recorder = trace_path.map(|_| TraceRecorder::for_solver(&solver));
+    if let Some(recorder) = &recorder {
+        solver.add_observer(Box::new(recorder.clone()));
+    }
+    let mut limits = SolveLimits::default();
+    if let Some(max_steps) = flags.iter().find_map(|flag| flag.strip_prefix("--max-steps=")) {
+        limits = limits.with_max_steps(max_steps.parse().expect("The step limit must be a non-negative integer"));
+    }
+    if let Some(timeout) = flags.iter().find_map(|flag| flag.strip_prefix("--timeout-ms=")) {
+        limits = limits.with_timeout(Duration::from_millis(timeout.parse().expect("The timeout must be a non-negative integer")));
+    }
     let start = Instant::now();
-    let solve_result = solver.solve();
-    let duration = start.elapsed();
-    println!("Solve time: {:?} ({})", duration, if solve_result {"success"} else {"failed"});
-}
\ No newline at end of file
+    if has_flag("--unique") {
+        let counts = solver.count_unique_solutions(has_flag("--reflections"));
+        println!("Enumeration time: {:?} ({} solutions, {} unique, seed {})", start.elapsed(), counts.raw, counts.unique, solver.seed());
+    } else if has_flag("--count") {
+        let solution_count = solver.count_solutions();
+        println!("Enumeration time: {:?} ({} solutions, seed {})", start.elapsed(), solution_count, solver.seed());
+    } else if let Some(schedule) = flags.iter().find_map(|flag| flag.strip_prefix("--restarts=")) {
+        let schedule = match schedule {
+            "luby" => RestartSchedule::luby(100),
+            "geometric" => RestartSchedule::geometric(100, 1.5),
+            _ => panic!("Unknown restart schedule {}, expected luby or geometric", schedule),
+        };
+        let report = solver.solve_with_restarts(schedule);
+        for (restart, stats) in report.restarts.iter().enumerate() {
+            println!("Restart {:>3}: seed {}, {:?} after {} of {} backtracks in {:?}", restart, stats.seed, stats.outcome, stats.backtracks, stats.cutoff, stats.duration);
+        }
+        print_solve_result(report.result, start.elapsed(), Some(solver.seed()));
+    } else if limits.max_steps.is_some() || limits.deadline.is_some() {
+        match solver.solve_with_limits(&limits) {
+            SolveOutcome::Solved(solution) => print_solve_result(Ok(solution), start.elapsed(), Some(solver.seed())),
+            SolveOutcome::Failed(err) => print_solve_result(Err(err), start.elapsed(), Some(solver.seed())),
+            SolveOutcome::LimitReached { limit, steps, partial, .. } => {
+                println!("Solve time: {:?} ({:?} limit reached after {} steps with {} pieces placed, seed {})", start.elapsed(), limit, steps, partial.len(), solver.seed())
+            }
+        }
+    } else {
+        let solve_result = solver.solve();
+        print_solve_result(solve_result, start.elapsed(), Some(solver.seed()));
+    }
+    println!("{}", solver.stats());
+    if let (Some(path), Some(recorder)) = (trace_path, recorder) {
+        let trace = recorder.trace();
+        trace.save(path).unwrap_or_else(|err| panic!("Failed to save trace to {}: {}", path, err));
+        println!("Saved a trace of {} events to {}", trace.events.len(), path);
+    }
+}
+
+/// Prints every event of the search as it happens
+#[derive(Debug)]
+struct EventLogger;
+
+impl SolverObserver for EventLogger {
+    fn cell_selected(&mut self, _grid: &Grid, x: usize, y: usize) {
+        println!("select ({}, {})", x, y);
+    }
+
+    fn piece_placed(&mut self, _grid: &Grid, solver_move: &SolverMove, depth: usize) {
+        println!("place  piece {:>2} {:?} at ({}, {}), depth {}", solver_move.piece_id, solver_move.orientation, solver_move.x, solver_move.y, depth);
+    }
+
+    fn piece_rejected(&mut self, _grid: &Grid, solver_move: &SolverMove, rejection: Rejection) {
+        println!("reject piece {:>2} {:?} at ({}, {}): {:?}", solver_move.piece_id, solver_move.orientation, solver_move.x, solver_move.y, rejection);
+    }
+
+    fn propagation_pruned(&mut self, _grid: &Grid, _solver_move: &SolverMove, prunes: usize) {
+        println!("prune  {} domains", prunes);
+    }
+
+    fn backtracked(&mut self, _grid: &Grid, depth: usize) {
+        println!("back   to depth {}", depth);
+    }
+
+    fn solution_found(&mut self, solution: &Solution) {
+        println!("solved with {} pieces", solution.placements.len());
+    }
+}
+
+fn print_solve_result(solve_result: Result<Solution, SolveError>, duration: Duration, seed: Option<u64>) {
+    let describe_seed = |seed: Option<u64>| seed.map(|seed| format!(", seed {}", seed)).unwrap_or_default();
+    match solve_result {
+        Ok(solution) => {
+            println!("Solve time: {:?} (success{})", duration, describe_seed(solution.seed));
+            for placement in solution.placements {
+                println!("  piece {:>2} {:?} at ({}, {})", placement.piece_id, placement.orientation, placement.x, placement.y);
+            }
+        }
+        Err(err) => println!("Solve time: {:?} (failed: {}{})", duration, err, describe_seed(seed)),
+    }
+}
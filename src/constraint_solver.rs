@@ -1,18 +1,25 @@
 use crate::constraint_solver::CellSolveState::{Solved, Unsolved};
 use crate::piece;
 use crate::piece::VisualCell::CellEmpty;
-use crate::piece::{Cell, PieceOps, VisualCell, get_full_cell_domain, get_piece_domain};
-use itertools::Itertools;
+use crate::piece::{Cell, CellSet, ConnectionType, PieceOps, VisualCell, cell_domain_for_pieces, get_full_cell_domain, get_piece_domain};
 use rand::rngs::SmallRng;
-use rand::seq::{IndexedRandom, SliceRandom};
 use rand::SeedableRng;
-use std::fmt::Debug;
+use crate::heuristics::{CellSelector, MinimumRemainingValues, PieceDomainOrder, PieceOrderer, RandomOrder, RandomTieBreak, RandomTieOrder};
+use crate::observer::{Rejection, SolverObserver};
+use crate::placements::{Placement, PlacementTable};
+use crate::validation::check_puzzle;
+use crate::symmetry::{SolutionCounts, SolutionTally};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use std::fmt::{Debug, Display, Formatter};
 
 pub const PUZZLE_WIDTH: usize = 6;
 pub const PUZZLE_HEIGHT: usize = 6;
-pub type Domain = Vec<Cell>;
+pub type Domain = CellSet;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Direction {
     Right,
     Up,
@@ -20,7 +27,20 @@ pub enum Direction {
     Down,
 }
 
-#[derive(Debug, Clone)]
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::Right, Direction::Up, Direction::Left, Direction::Down];
+
+    pub const fn opposite(&self) -> Direction {
+        match self {
+            Direction::Right => Direction::Left,
+            Direction::Up => Direction::Down,
+            Direction::Left => Direction::Right,
+            Direction::Down => Direction::Up,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum CellSolveState {
     Solved(Cell),
     Unsolved(Domain),
@@ -28,83 +48,247 @@ pub enum CellSolveState {
 
 #[derive(Debug, Clone)]
 pub struct Grid {
-    pub grid: [[CellSolveState; PUZZLE_WIDTH]; PUZZLE_HEIGHT],
-    pub visual_grid: [[VisualCell; PUZZLE_WIDTH]; PUZZLE_WIDTH],
+    pub width: usize,
+    pub height: usize,
+    pub grid: Vec<Vec<CellSolveState>>,
+    pub visual_grid: Vec<Vec<VisualCell>>,
 
     pub pieces_left: Vec<&'static dyn PieceOps>,
+
+    /// Every change made to the grid, oldest first, so they can be undone in reverse
+    changes: Vec<GridChange>,
+
+    placement_table: Arc<PlacementTable>,
+    /// Which placements in the table are still legal: the piece is left and every cell it
+    /// would cover is unsolved and still has the piece's cell in its domain
+    live_placements: Vec<bool>,
+    /// How many legal placements each piece has left, indexed like the piece domain
+    live_placements_per_piece: Vec<usize>,
+    /// How many legal placements cover each cell, row-major
+    coverage: Vec<usize>,
+    /// How many placements propagation has made illegal so far, undoing doesn't bring it back down
+    pruned_placements: usize,
+}
+
+/// A single change to a [`Grid`], holding what was there before so it can be undone
+#[derive(Debug, Clone)]
+enum GridChange {
+    Cell { x: usize, y: usize, previous: CellSolveState },
+    VisualCell { x: usize, y: usize, previous: VisualCell },
+    PieceUsed { index: usize, piece: &'static dyn PieceOps },
+    PlacementRemoved { index: usize },
+}
+
+/// Every cell whose edge on the `side` side has the given connection type
+fn cells_with_edge(side: Direction, connection: ConnectionType) -> CellSet {
+    static CELLS_WITH_EDGE: LazyLock<[[CellSet; 3]; 4]> = LazyLock::new(|| {
+        let connections = [ConnectionType::NoConnection, ConnectionType::Straight, ConnectionType::Double];
+        Direction::ALL.map(|side| {
+            connections.map(|connection| {
+                CellSet::all_matching(|cell| {
+                    let edge = match side {
+                        Direction::Right => cell.right,
+                        Direction::Up => cell.top,
+                        Direction::Left => cell.left,
+                        Direction::Down => cell.bottom,
+                    };
+                    edge == connection
+                })
+            })
+        })
+    });
+    CELLS_WITH_EDGE[side as usize][connection as usize]
+}
+
+/// Every cell whose edge on the `side` side can connect to some cell in `neighbor`, the domain of the cell on that side
+fn cells_supported_by(side: Direction, neighbor: CellSet) -> CellSet {
+    let mut supported = CellSet::EMPTY;
+    for connection in [ConnectionType::NoConnection, ConnectionType::Straight, ConnectionType::Double] {
+        if neighbor.intersects(cells_with_edge(side.opposite(), connection)) {
+            supported |= cells_with_edge(side, connection);
+        }
+    }
+    supported
 }
 
 impl Default for Grid {
     fn default() -> Self {
-        let full_domain = get_full_cell_domain();
-        let full_domain_cell_state = Unsolved(full_domain.clone());
-        let pieces_left = get_piece_domain().clone();
-        Grid {
-            grid: [
-                [
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                ],
-                [
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                ],
-                [
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                ],
-                [
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                ],
-                [
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                ],
-                [
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                    full_domain_cell_state.clone(),
-                ],
-            ],
-            visual_grid: [[CellEmpty; PUZZLE_WIDTH]; PUZZLE_HEIGHT],
-            pieces_left,
-        }
+        Grid::new(PUZZLE_WIDTH, PUZZLE_HEIGHT)
     }
 }
 
 impl Grid {
+    pub fn new(width: usize, height: usize) -> Grid {
+        Grid::with_cell_domain(width, height, get_piece_domain(), get_full_cell_domain())
+    }
+
+    pub fn with_pieces(width: usize, height: usize, pieces: &[&'static dyn PieceOps]) -> Grid {
+        Grid::with_cell_domain(width, height, pieces, &cell_domain_for_pieces(pieces))
+    }
+
+    fn with_cell_domain(width: usize, height: usize, pieces: &[&'static dyn PieceOps], full_domain: &[Cell]) -> Grid {
+        let full_domain_cell_state = Unsolved(full_domain.iter().copied().collect());
+        let pieces_left = pieces.to_vec();
+        let placement_table = Arc::new(PlacementTable::new(width, height, pieces));
+        let mut grid = Grid {
+            width,
+            height,
+            grid: vec![vec![full_domain_cell_state; width]; height],
+            visual_grid: vec![vec![CellEmpty; width]; height],
+            pieces_left,
+            changes: vec![],
+            live_placements: vec![true; placement_table.placements.len()],
+            live_placements_per_piece: (0..placement_table.piece_count).map(|piece_index| placement_table.of_piece(piece_index).len()).collect(),
+            coverage: (0..width * height).map(|i| placement_table.covering(i % width, i / width).len()).collect(),
+            placement_table,
+            pruned_placements: 0,
+        };
+        // Cells along the border can only have edges without a connection facing it.
+        // If that already empties a domain (or leaves a piece nowhere to go) the grid simply fails its first check.
+        grid.do_constraint_propagation();
+        grid.prune_placements(0);
+        grid.changes.clear();
+        grid.pruned_placements = 0;
+        grid
+    }
+
+    pub fn placement_table(&self) -> &Arc<PlacementTable> {
+        &self.placement_table
+    }
+
+    pub fn is_placement_live(&self, index: usize) -> bool {
+        self.live_placements[index]
+    }
+
+    /// How many legal placements the piece at `piece_index` (in the placement table) has left
+    pub fn live_placement_count(&self, piece_index: usize) -> usize {
+        self.live_placements_per_piece[piece_index]
+    }
+
+    /// How many placements propagation has made illegal over the grid's lifetime, placements of a piece that
+    /// is used up aren't counted and undoing doesn't lower it
+    pub fn pruned_placements(&self) -> usize {
+        self.pruned_placements
+    }
+
+    /// How many legal placements cover the cell at (`x`, `y`)
+    pub fn coverage(&self, x: usize, y: usize) -> usize {
+        self.coverage[y * self.width + x]
+    }
+
+    fn is_placement_legal(&self, placement: &Placement) -> bool {
+        placement.cells.iter().all(|(x, y, cell)| matches!(self.grid[*y][*x], Unsolved(domain) if domain.contains(cell)))
+    }
+
+    fn remove_placement(&mut self, index: usize) {
+        if !self.live_placements[index] {
+            return;
+        }
+        let placement = &self.placement_table.placements[index];
+        self.live_placements[index] = false;
+        self.live_placements_per_piece[placement.piece_index] -= 1;
+        for (x, y, _) in &placement.cells {
+            self.coverage[y * self.width + x] -= 1;
+        }
+        self.changes.push(GridChange::PlacementRemoved { index });
+    }
+
+    /// Removes the placements made illegal by cells that changed since `checkpoint`.
+    /// Returns false if a piece is left without any placement or a cell can't be covered anymore.
+    fn prune_placements(&mut self, checkpoint: usize) -> bool {
+        let changed_cells = self.changes[checkpoint..].iter().filter_map(|change| if let GridChange::Cell { x, y, .. } = change { Some((*x, *y)) } else { None }).collect::<Vec<_>>();
+        let placement_table = Arc::clone(&self.placement_table);
+        for (x, y) in changed_cells {
+            for &index in placement_table.covering(x, y) {
+                if self.live_placements[index] && !self.is_placement_legal(&placement_table.placements[index]) {
+                    self.remove_placement(index);
+                    self.pruned_placements += 1;
+                }
+            }
+        }
+        self.placements_feasible()
+    }
+
+    /// True if every piece left can still go somewhere and every unsolved cell can still be covered by some piece
+    pub fn placements_feasible(&self) -> bool {
+        let pieces_placeable = self.pieces_left.iter().all(|piece| self.live_placements_per_piece[self.placement_table.piece_index(piece.piece_id())] > 0);
+        pieces_placeable && self.grid.iter().flatten().zip(&self.coverage).all(|(cell, coverage)| matches!(cell, Solved(_)) || *coverage > 0)
+    }
+
+    /// A marker for the grid's current state, which [`Grid::undo_to`] can later return to
+    pub fn checkpoint(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Reverts every change made since `checkpoint` was taken
+    pub fn undo_to(&mut self, checkpoint: usize) {
+        while self.changes.len() > checkpoint {
+            match self.changes.pop().unwrap() {
+                GridChange::Cell { x, y, previous } => self.grid[y][x] = previous,
+                GridChange::VisualCell { x, y, previous } => self.visual_grid[y][x] = previous,
+                GridChange::PieceUsed { index, piece } => self.pieces_left.insert(index, piece),
+                GridChange::PlacementRemoved { index } => {
+                    let placement = &self.placement_table.placements[index];
+                    self.live_placements[index] = true;
+                    self.live_placements_per_piece[placement.piece_index] += 1;
+                    for (x, y, _) in &placement.cells {
+                        self.coverage[y * self.width + x] += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// How many times a cell was changed since `checkpoint`, by being solved or having its domain pruned
+    pub fn cell_changes_since(&self, checkpoint: usize) -> usize {
+        self.changes[checkpoint..].iter().filter(|change| matches!(change, GridChange::Cell { .. })).count()
+    }
+
+    /// A copy of the grid without the history needed to undo changes
+    pub fn snapshot(&self) -> Grid {
+        Grid {
+            width: self.width,
+            height: self.height,
+            grid: self.grid.clone(),
+            visual_grid: self.visual_grid.clone(),
+            pieces_left: self.pieces_left.clone(),
+            changes: vec![],
+            placement_table: Arc::clone(&self.placement_table),
+            live_placements: self.live_placements.clone(),
+            live_placements_per_piece: self.live_placements_per_piece.clone(),
+            coverage: self.coverage.clone(),
+            pruned_placements: self.pruned_placements,
+        }
+    }
+
+    fn set_cell(&mut self, x: usize, y: usize, cell_state: CellSolveState) {
+        let previous = std::mem::replace(&mut self.grid[y][x], cell_state);
+        self.changes.push(GridChange::Cell { x, y, previous });
+    }
+
+    fn set_visual_cell(&mut self, x: usize, y: usize, visual_cell: VisualCell) {
+        let previous = std::mem::replace(&mut self.visual_grid[y][x], visual_cell);
+        self.changes.push(GridChange::VisualCell { x, y, previous });
+    }
+
+    fn use_piece(&mut self, piece: &dyn PieceOps) {
+        if let Some(index) = self.pieces_left.iter().position(|p| piece == *p) {
+            let piece = self.pieces_left.remove(index);
+            self.changes.push(GridChange::PieceUsed { index, piece });
+        }
+        for index in self.placement_table.of_piece(self.placement_table.piece_index(piece.piece_id())) {
+            self.remove_placement(index);
+        }
+    }
+
     fn get_neighbor_cell(&self, x: usize, y: usize, direction: Direction) -> Option<&Cell> {
-        const MAX_X: usize = PUZZLE_WIDTH - 1;
-        const MAX_Y: usize = PUZZLE_HEIGHT - 1;
+        let max_x = self.width - 1;
+        let max_y = self.height - 1;
         match (x, y, direction) {
-            (x, _, Direction::Right) if x >= MAX_X => Some(&piece::CELL_EMPTY),
+            (x, _, Direction::Right) if x >= max_x => Some(&piece::CELL_EMPTY),
             (_, 0, Direction::Up) => Some(&piece::CELL_EMPTY),
             (0, _, Direction::Left) => Some(&piece::CELL_EMPTY),
-            (_, y, Direction::Down) if y >= MAX_Y => Some(&piece::CELL_EMPTY),
+            (_, y, Direction::Down) if y >= max_y => Some(&piece::CELL_EMPTY),
             (x, y, direction) => {
                 let (x, y) = match direction {
                     Direction::Right => (x + 1, y),
@@ -126,9 +310,12 @@ impl Grid {
                 }
             }
         }
+        if !self.placements_feasible() {
+            return false;
+        }
 
-        for y in 0..6usize {
-            for x in 0..6usize {
+        for y in 0..self.height {
+            for x in 0..self.width {
                 let curr_cell = &self.grid[y][x];
                 if let Solved(curr_cell) = curr_cell {
                     let neighbor_right = self.get_neighbor_cell(x, y, Direction::Right).cloned();
@@ -162,42 +349,80 @@ impl Grid {
         true
     }
 
-    pub fn do_constraint_propagation(&mut self) {
-        for y in 0..6usize {
-            for x in 0..6usize {
-                let neighbor_right = self.get_neighbor_cell(x, y, Direction::Right).cloned();
-                let neighbor_top = self.get_neighbor_cell(x, y, Direction::Up).cloned();
-                let neighbor_left = self.get_neighbor_cell(x, y, Direction::Left).cloned();
-                let neighbor_bottom = self.get_neighbor_cell(x, y, Direction::Down).cloned();
-                // TODO: More constraints (Can't have 2 of the same cell edges diagonal from each other)
-                let curr_cell = &mut self.grid[y][x];
-                if let Unsolved(domain) = curr_cell {
-                    domain.retain(|cell| {
-                        if let Some(other) = neighbor_right {
-                            if cell.right != other.left {
-                                return false;
-                            }
-                        }
-                        if let Some(other) = neighbor_top {
-                            if cell.top != other.bottom {
-                                return false;
-                            }
-                        }
-                        if let Some(other) = neighbor_left {
-                            if cell.left != other.right {
-                                return false;
-                            }
-                        }
-                        if let Some(other) = neighbor_bottom {
-                            if cell.bottom != other.top {
-                                return false;
-                            }
-                        }
-                        true
-                    });
+    /// Makes every unsolved cell's domain consistent with all its neighbours, see [`Grid::propagate_from`]
+    pub fn do_constraint_propagation(&mut self) -> bool {
+        let all_cells = (0..self.height).flat_map(|y| (0..self.width).map(move |x| (x, y))).collect::<Vec<_>>();
+        self.propagate_from(all_cells)
+    }
+
+    /// Arc consistency (AC-3) starting from the given cells: a cell's domain only keeps cells whose edges
+    /// can match some cell in each neighbour's domain (or solved cell, or the border). Whenever a domain
+    /// shrinks its neighbours are revised again, until nothing changes.
+    /// Returns false as soon as a domain becomes empty, the grid can't be solved from there.
+    pub fn propagate_from(&mut self, cells: impl IntoIterator<Item = (usize, usize)>) -> bool {
+        let mut worklist = VecDeque::new();
+        let mut queued = vec![vec![false; self.width]; self.height];
+        for (x, y) in cells {
+            if !queued[y][x] {
+                queued[y][x] = true;
+                worklist.push_back((x, y));
+            }
+        }
+
+        while let Some((x, y)) = worklist.pop_front() {
+            queued[y][x] = false;
+            let Unsolved(domain) = self.grid[y][x] else {
+                continue;
+            };
+            // TODO: More constraints (Can't have 2 of the same cell edges diagonal from each other)
+            let mut pruned = domain;
+            for direction in Direction::ALL {
+                pruned &= cells_supported_by(direction, self.neighbor_domain(x, y, direction));
+            }
+            if pruned == domain {
+                continue;
+            }
+            self.set_cell(x, y, Unsolved(pruned));
+            if pruned.is_empty() {
+                return false;
+            }
+            for direction in Direction::ALL {
+                if let Some((nx, ny)) = self.neighbor_position(x, y, direction)
+                    && !queued[ny][nx]
+                    && matches!(self.grid[ny][nx], Unsolved(_))
+                {
+                    queued[ny][nx] = true;
+                    worklist.push_back((nx, ny));
                 }
             }
         }
+        true
+    }
+
+    pub(crate) fn neighbor_position(&self, x: usize, y: usize, direction: Direction) -> Option<(usize, usize)> {
+        match direction {
+            Direction::Right if x + 1 < self.width => Some((x + 1, y)),
+            Direction::Up if y > 0 => Some((x, y - 1)),
+            Direction::Left if x > 0 => Some((x - 1, y)),
+            Direction::Down if y + 1 < self.height => Some((x, y + 1)),
+            _ => None,
+        }
+    }
+
+    /// The cells the neighbour in `direction` could still be, the border counts as an empty cell
+    fn neighbor_domain(&self, x: usize, y: usize, direction: Direction) -> CellSet {
+        match self.neighbor_position(x, y, direction) {
+            None => CellSet::from_iter([piece::CELL_EMPTY]),
+            Some((nx, ny)) => match self.grid[ny][nx] {
+                Solved(cell) => CellSet::from_iter([cell]),
+                Unsolved(domain) => domain,
+            },
+        }
+    }
+
+    /// True if every cell is solved, every piece has been used and all edges match
+    pub fn is_solved(&self) -> bool {
+        self.pieces_left.is_empty() && self.grid.iter().flatten().all(|cell| matches!(cell, Solved(_))) && self.check()
     }
 
     pub fn can_place_piece(&self, piece: &dyn PieceOps, x: usize, y: usize) -> bool {
@@ -206,17 +431,23 @@ impl Grid {
         let width = piece.width();
         let height = piece.height();
 
-        if x + width > 6 || y + height > 6 {
+        if x + width > self.width || y + height > self.height {
             return false;
         }
 
+        let piece_cells = piece.cells_flat();
         for local_x in 0..width {
             for local_y in 0..height {
                 let gx = local_x + x;
                 let gy = local_y + y;
-                if let Solved(_) = self.grid[gy][gx] {
-                    // println!("Tried to place piece which would overwrite Solved cell at {}, {}", gx, gy);
-                    return false;
+                match self.grid[gy][gx] {
+                    Solved(_) => {
+                        // println!("Tried to place piece which would overwrite Solved cell at {}, {}", gx, gy);
+                        return false;
+                    }
+                    // Propagation already removed every cell that can't match the neighbours
+                    Unsolved(domain) if !domain.contains(&piece_cells[local_y * width + local_x]) => return false,
+                    Unsolved(_) => {}
                 }
             }
         }
@@ -227,7 +458,9 @@ impl Grid {
         true
     }
 
-    pub fn place_piece_unchecked(&mut self, piece: &dyn PieceOps, x: usize, y: usize) {
+    /// Places the piece without checking it fits, returns false if propagation found the grid can no longer be solved
+    pub fn place_piece_unchecked(&mut self, piece: &dyn PieceOps, x: usize, y: usize) -> bool {
+        let checkpoint = self.checkpoint();
         let width = piece.width();
         let height = piece.height();
 
@@ -236,17 +469,32 @@ impl Grid {
             for local_y in 0..height {
                 let gx = local_x + x;
                 let gy = local_y + y;
-                self.grid[gy][gx] = Solved(piece_cells[local_y * width + local_x]);
+                self.set_cell(gx, gy, Solved(piece_cells[local_y * width + local_x]));
             }
         }
-        self.do_constraint_propagation();
-        self.pieces_left.retain(|p| piece != *p);
+        self.use_piece(piece);
         let visual_cells = piece.visual_cells_flat();
         for local_x in 0..width {
             for local_y in 0..height {
-                self.visual_grid[local_y + y][local_x + x] = visual_cells[local_y * width + local_x];
+                self.set_visual_cell(local_x + x, local_y + y, visual_cells[local_y * width + local_x]);
             }
         }
+        self.propagate_from(self.cells_around(x, y, width, height)) && self.prune_placements(checkpoint)
+    }
+
+    /// The cells bordering the `width` x `height` area at (`x`, `y`)
+    fn cells_around(&self, x: usize, y: usize, width: usize, height: usize) -> Vec<(usize, usize)> {
+        let mut cells = vec![];
+        for local_y in 0..height {
+            for local_x in 0..width {
+                for direction in Direction::ALL {
+                    if let Some(neighbor) = self.neighbor_position(x + local_x, y + local_y, direction) {
+                        cells.push(neighbor);
+                    }
+                }
+            }
+        }
+        cells
     }
 
     // 00 10 20 30 40 50
@@ -261,7 +509,7 @@ impl Grid {
         let width = piece.width();
         let height = piece.height();
 
-        if x + width > 6 || y + height > 6 {
+        if x + width > self.width || y + height > self.height {
             return Err("Piece cell outside bounds");
         }
 
@@ -293,19 +541,19 @@ impl Grid {
                 }
             }
         }
+        let checkpoint = self.checkpoint();
         for change in changes {
-            self.grid[change.0][change.1] = change.2;
+            self.set_cell(change.1, change.0, change.2);
         }
-        self.do_constraint_propagation();
-        if !self.check() {
+        self.use_piece(piece);
+        if !self.propagate_from(self.cells_around(x, y, width, height)) || !self.prune_placements(checkpoint) || !self.check() {
             return Err("Failed check after placing piece");
         }
-        self.pieces_left.retain(|p| piece != *p);
 
         let visual_cells = piece.visual_cells_flat();
         for local_x in 0..width {
             for local_y in 0..height {
-                self.visual_grid[local_y + y][local_x + x] = visual_cells[local_y * width + local_x];
+                self.set_visual_cell(local_x + x, local_y + y, visual_cells[local_y * width + local_x]);
             }
         }
 
@@ -313,153 +561,649 @@ impl Grid {
     }
 }
 
+/// Placing a piece in some orientation with its top left corner at (`x`, `y`)
 #[derive(Debug, PartialEq, Clone)]
 pub struct SolverMove {
-    piece_id: usize,
-    rotation: PieceRotation,
-    x: usize,
-    y: usize,
+    pub piece_id: usize,
+    pub orientation: PieceOrientation,
+    pub x: usize,
+    pub y: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+    /// Every piece in the order it was placed
+    pub placements: Vec<SolverMove>,
+    pub grid: Grid,
+    /// The seed of the [`SolverState`] that found the solution, whether or not its heuristics drew from it
+    /// (a custom heuristic can be random without [`SolverConfig::randomize`]). Solvers that never make random choices leave it empty.
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolveError {
+    /// Every branch was tried without finding a (further) solution
+    Exhausted,
+    /// The puzzle can't be solved no matter how the search goes, with every problem found
+    InvalidInput(Vec<String>),
+}
+
+impl Display for SolveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolveError::Exhausted => write!(f, "Exhausted the search space without finding a solution"),
+            SolveError::InvalidInput(diagnostics) => write!(f, "Invalid puzzle: {}", diagnostics.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+/// Counters of how much work the search did, they keep adding up across restarts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolverStats {
+    pub steps: usize,
+    /// Placements on the chosen cells that hadn't been tried on their level yet
+    pub placements_attempted: usize,
+    /// Attempted placements that [`Grid::can_place_piece`] turned down
+    pub placements_rejected: usize,
+    /// Placements that fit, but propagation showed they can't lead to a solution
+    pub placements_failed: usize,
+    /// Placements propagation made illegal before the search got to try them, see [`Grid::pruned_placements`]
+    pub placements_pruned: usize,
+    pub backtracks: usize,
+    pub max_depth: usize,
+    pub propagations: usize,
+    /// How many times propagation shrank a cell's domain, over every propagation
+    pub domain_prunes: usize,
+    pub propagation_time: Duration,
+    /// Time spent in steps outside propagation: choosing cells, generating and checking moves and backtracking
+    pub move_generation_time: Duration,
+}
+
+impl SolverStats {
+    pub fn prunes_per_propagation(&self) -> f64 {
+        if self.propagations == 0 { 0.0 } else { self.domain_prunes as f64 / self.propagations as f64 }
+    }
+}
+
+impl Display for SolverStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "steps: {}, backtracks: {}, max depth: {}", self.steps, self.backtracks, self.max_depth)?;
+        writeln!(f, "placements: {} attempted, {} rejected, {} failed propagation, {} pruned", self.placements_attempted, self.placements_rejected, self.placements_failed, self.placements_pruned)?;
+        writeln!(f, "propagations: {}, {} domain prunes ({:.1} per propagation)", self.propagations, self.domain_prunes, self.prunes_per_propagation())?;
+        write!(f, "time: {:?} propagating, {:?} generating moves", self.propagation_time, self.move_generation_time)
+    }
+}
+
+/// Bounds on how long [`SolverState::solve_with_limits`] may search, no limits are set by default
+#[derive(Debug, Clone, Default)]
+pub struct SolveLimits {
+    pub max_steps: Option<usize>,
+    pub deadline: Option<Instant>,
+    /// Stops the search once another thread sets it
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl SolveLimits {
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// A deadline `timeout` from now
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    fn reached(&self, steps: usize) -> Option<Limit> {
+        if self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            Some(Limit::Cancelled)
+        } else if self.max_steps.is_some_and(|max_steps| steps >= max_steps) {
+            Some(Limit::Steps)
+        } else if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Some(Limit::Deadline)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Steps,
+    Deadline,
+    Cancelled,
+}
+
+#[derive(Debug)]
+pub enum SolveOutcome {
+    Solved(Solution),
+    Failed(SolveError),
+    /// The search stopped before finishing, with the pieces placed so far and the grid they make up
+    LimitReached { limit: Limit, steps: usize, partial: Vec<SolverMove>, grid: Grid },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolverConfig {
+    /// Seeds every random choice the search makes, running again with the same seed reproduces the same search
+    pub seed: u64,
+    /// Break ties between cells at random and try placements in a random order,
+    /// instead of [`MinimumRemainingValues`] and [`PieceDomainOrder`]
+    pub randomize: bool,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        SolverConfig { seed: 69, randomize: false }
+    }
+}
+
+impl SolverConfig {
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn randomized(mut self) -> Self {
+        self.randomize = true;
+        self
+    }
 }
 
 #[derive(Debug)]
 pub struct SolverState {
-    pub grid_stack: Vec<Grid>,
+    grid: Grid,
+    /// The grid's checkpoint from before each placement on the current search path
+    checkpoints: Vec<usize>,
+    /// Every piece the puzzle started with
+    pieces: Vec<&'static dyn PieceOps>,
 
     // Wait a second, that just sounds like recursion with extra steps!
     pub tried_branches: Vec<Vec<SolverMove>>,
+    /// The cell each level branches on, kept once chosen so a level never mixes moves for different cells
+    branch_cells: Vec<Option<(usize, usize)>>,
+    /// Placements made for good before the search started, see [`SolverState::fix_placement`]
+    fixed_moves: Vec<SolverMove>,
 
+    /// Rotations still allowed for one piece, used to break the board's rotational symmetry
+    symmetry_restriction: Option<(usize, Vec<PieceRotation>)>,
+
+    /// Every placement of every piece, shared with the grid
+    placement_table: Arc<PlacementTable>,
+
+    /// Picks the cell to branch on at every step
+    cell_selector: Box<dyn CellSelector>,
+    /// Orders the placements tried on the chosen cell
+    piece_orderer: Box<dyn PieceOrderer>,
+
+    config: SolverConfig,
     rng: SmallRng,
+    /// Counters of the work done so far, over every run
+    stats: SolverStats,
+    /// Not carried over when the solver is forked
+    observers: Vec<Box<dyn SolverObserver>>,
+}
+
+impl Default for SolverState {
+    fn default() -> Self {
+        SolverState::new(PUZZLE_WIDTH, PUZZLE_HEIGHT)
+    }
 }
 
 impl SolverState {
-    pub fn new() -> SolverState {
-        let starting_grid = Grid::default();
-        SolverState { grid_stack: vec![starting_grid], tried_branches: vec![vec![]], rng: SmallRng::seed_from_u64(69) }
+    pub fn new(width: usize, height: usize) -> SolverState {
+        SolverState::from_grid(Grid::new(width, height))
+    }
+
+    pub fn with_pieces(width: usize, height: usize, pieces: &[&'static dyn PieceOps]) -> SolverState {
+        SolverState::from_grid(Grid::with_pieces(width, height, pieces))
+    }
+
+    pub fn with_config(width: usize, height: usize, pieces: &[&'static dyn PieceOps], config: SolverConfig) -> SolverState {
+        let mut solver = SolverState::with_pieces(width, height, pieces);
+        solver.apply_config(config);
+        solver
+    }
+
+    fn apply_config(&mut self, config: SolverConfig) {
+        if config.randomize {
+            self.cell_selector = Box::new(RandomTieBreak(MinimumRemainingValues));
+            self.piece_orderer = Box::new(RandomOrder);
+        }
+        self.rng = SmallRng::seed_from_u64(config.seed);
+        self.config = config;
+    }
+
+    /// Keeps the current heuristics but breaks their ties at random, so reseeding changes the search
+    pub(crate) fn randomize_ties(&mut self) {
+        let cell_selector = std::mem::replace(&mut self.cell_selector, Box::new(MinimumRemainingValues));
+        self.cell_selector = Box::new(RandomTieBreak(cell_selector));
+        let piece_orderer = std::mem::replace(&mut self.piece_orderer, Box::new(PieceDomainOrder));
+        self.piece_orderer = Box::new(RandomTieOrder(piece_orderer));
+    }
+
+    /// A new solver starting from this one's root, with its fixed placements and symmetry restriction.
+    /// Heuristics are set up from the config again, so ones replaced with a setter aren't carried over.
+    pub(crate) fn fork(&self) -> SolverState {
+        debug_assert_eq!(self.depth(), 0, "Only a solver that hasn't started searching can be forked");
+        let mut solver = SolverState::from_grid(self.grid.snapshot());
+        solver.pieces = self.pieces.clone();
+        solver.fixed_moves = self.fixed_moves.clone();
+        solver.symmetry_restriction = self.symmetry_restriction.clone();
+        solver.apply_config(self.config.clone());
+        solver
+    }
+
+    /// Places a piece for good before searching, the search never backtracks past it.
+    /// Returns false if the placement doesn't fit or leaves the puzzle unsolvable.
+    pub(crate) fn fix_placement(&mut self, index: usize) -> bool {
+        debug_assert_eq!(self.depth(), 0, "Placements can only be fixed before searching");
+        let placement = &self.placement_table.placements[index];
+        let piece = self.placement_table.oriented_piece(placement);
+        if !self.grid.can_place_piece(piece, placement.x, placement.y) || !self.grid.place_piece_unchecked(piece, placement.x, placement.y) {
+            return false;
+        }
+        self.fixed_moves.push(SolverMove { piece_id: placement.piece_id, orientation: placement.orientation, x: placement.x, y: placement.y });
+        true
+    }
+
+    /// The cell the search would branch on first, with the placements it would try there in order
+    pub(crate) fn root_branches(&mut self) -> Vec<usize> {
+        debug_assert_eq!(self.depth(), 0);
+        match self.cell_selector.select(&self.grid, &mut self.rng) {
+            Some((x, y)) => self.candidate_placements(x, y),
+            None => vec![],
+        }
+    }
+
+    /// The placements covering (`x`, `y`) that are still legal and allowed by the symmetry restriction, in the order to try them
+    fn candidate_placements(&mut self, x: usize, y: usize) -> Vec<usize> {
+        let placement_table = &self.placement_table;
+        // Propagation already removed every placement that can't go here
+        let mut candidates = placement_table
+            .covering(x, y)
+            .iter()
+            .copied()
+            .filter(|index| self.grid.is_placement_live(*index))
+            .filter(|index| {
+                let placement = &placement_table.placements[*index];
+                !matches!(&self.symmetry_restriction, Some((restricted_piece_id, allowed_rotations)) if *restricted_piece_id == placement.piece_id && !allowed_rotations.contains(&placement.orientation.rotation()))
+            })
+            .collect::<Vec<_>>();
+        self.piece_orderer.order(&self.grid, &mut candidates, &mut self.rng);
+        candidates
+    }
+
+    fn from_grid(starting_grid: Grid) -> SolverState {
+        let config = SolverConfig::default();
+        let placement_table = Arc::clone(starting_grid.placement_table());
+        let pieces = starting_grid.pieces_left.clone();
+        SolverState {
+            grid: starting_grid,
+            checkpoints: vec![],
+            pieces,
+            tried_branches: vec![vec![]],
+            branch_cells: vec![None],
+            fixed_moves: vec![],
+            symmetry_restriction: None,
+            placement_table,
+            cell_selector: Box::new(MinimumRemainingValues),
+            piece_orderer: Box::new(PieceDomainOrder),
+            rng: SmallRng::seed_from_u64(config.seed),
+            config,
+            stats: SolverStats::default(),
+            observers: vec![],
+        }
     }
 
     pub fn step_propagate(&mut self) -> Result<(), ()> {
+        let step_start = Instant::now();
+        let propagation_time_before = self.stats.propagation_time;
+        let result = self.take_step();
+        self.stats.steps += 1;
+        self.stats.move_generation_time += step_start.elapsed().saturating_sub(self.stats.propagation_time - propagation_time_before);
+        result
+    }
+
+    fn take_step(&mut self) -> Result<(), ()> {
         // Push state
         // Try to place a piece
         // If find piece to place, done
         // If can't find piece to place, pop and try again
         // If nothing left to pop, failed
 
-        #[derive(Debug)]
-        struct DomainWithPosition {
-            domain: Domain,
-            x: usize,
-            y: usize,
-        }
-
-        let cells_by_entropy = self
-            .current_grid()
-            .grid
-            .iter()
-            .enumerate()
-            .map(|(y, row)| row.iter().enumerate().map(move |(x, cell_state)| if let Unsolved(domain) = cell_state { Some(DomainWithPosition { domain: domain.clone(), x, y }) } else { None }))
-            .flatten()
-            .flatten()
-            .sorted_by(|d1, d2| d1.domain.len().cmp(&d2.domain.len()))
-            .next();
-        let cells_by_entropy = if let Some(cells_by_entropy) = cells_by_entropy {
-            cells_by_entropy
-        } else {
+        let branch_cell = match self.branch_cells.last().unwrap() {
+            Some(cell) => Some(*cell),
+            None => {
+                let selected = self.cell_selector.select(&self.grid, &mut self.rng);
+                if let Some((x, y)) = selected {
+                    self.notify(|observer, grid| observer.cell_selected(grid, x, y));
+                }
+                selected
+            }
+        };
+        let (x, y) = if let Some(cell) = branch_cell {
+            *self.branch_cells.last_mut().unwrap() = Some(cell);
+            cell
+        } else if self.current_grid().is_solved() || self.depth() == 0 {
             return Err(());
+        } else {
+            // Every cell is filled but the grid isn't a valid solution, backtrack
+            self.backtrack();
+            return Ok(());
         };
 
         let mut placed_piece = false;
 
-        let domain = cells_by_entropy;
-        // domain.domain.shuffle(&mut self.rng);
-        // let chosen_cell = domain.domain.choose(&mut self.rng);
-        // TODO: Choose pieces better
-        'outer:
-            for piece in self.current_grid().pieces_left.clone() {
-                let pid = piece.piece_id();
-                'rot: for rotation in PieceRotation::ROTATIONS {
-                    let permutation = piece.rotate(rotation);
-                    // if !permutation.cells().iter().flatten().contains(chosen_cell) {
-                    //     // If the permutation doesn't contain the chosen cell, then we don't have to try it
-                    //     continue;
-                    // }
-                    'cont:{
-                        for cell in &domain.domain {
-                            if permutation.cells_flat().contains(cell) {
-                                // If the permutation doesn't contain the chosen cell, then we don't have to try it
-                                break 'cont;
-                            }
-                        }
-                        continue 'rot;
-                    }
-                    for local_x in 0..permutation.width() {
-                        for local_y in 0..permutation.height() {
-                            if local_x > domain.x || local_y > domain.y {
-                                continue;
-                            }
-                            let solver_move = SolverMove { piece_id: pid, rotation, x: domain.x - local_x, y: domain.y - local_y };
-                            if self.tried_branches.last().unwrap().contains(&solver_move) {
-                                // println!("Skipping move because it already failed");
-                                continue;
-                            }
-                            let can_place_piece = self.current_grid().can_place_piece(&*permutation, solver_move.x, solver_move.y);
-                            // let place_result = self.current_grid_mut().place_piece(&*permutation, solver_move.x, solver_move.y);
-                            if can_place_piece {
-                                self.tried_branches.last_mut().unwrap().push(solver_move.clone());
-                                self.push_state();
-                                self.current_grid_mut().place_piece_unchecked(&*permutation, solver_move.x, solver_move.y);
-                                // println!("placed piece at {}, {}", domain.x, domain.y);
-                                placed_piece = true;
-                                break 'outer;
-                            } else {
-                                // self.pop_state();
-                                // println!(
-                                //     "Tried to place piece {} rotation {:?} at ({}, {}), failed.",
-                                //     permutation.piece_id(),
-                                //     rotation,
-                                //     domain.x,
-                                //     domain.y
-                                // );
-                            }
-                        }
-                    }
+        let placement_table = Arc::clone(&self.placement_table);
+        for index in self.candidate_placements(x, y) {
+            let placement = &placement_table.placements[index];
+            let solver_move = SolverMove { piece_id: placement.piece_id, orientation: placement.orientation, x: placement.x, y: placement.y };
+            if self.tried_branches.last().unwrap().contains(&solver_move) {
+                // println!("Skipping move because it already failed");
+                continue;
+            }
+            let permutation = placement_table.oriented_piece(placement);
+            self.stats.placements_attempted += 1;
+            if self.current_grid().can_place_piece(permutation, solver_move.x, solver_move.y) {
+                self.tried_branches.last_mut().unwrap().push(solver_move.clone());
+                self.push_state();
+                let propagation_start = Instant::now();
+                let pruned_before = self.grid.pruned_placements();
+                let placed = self.grid.place_piece_unchecked(permutation, solver_move.x, solver_move.y);
+                self.stats.propagation_time += propagation_start.elapsed();
+                self.stats.placements_pruned += self.grid.pruned_placements() - pruned_before;
+                self.stats.propagations += 1;
+                let prunes = self.grid.cell_changes_since(*self.checkpoints.last().unwrap()) - permutation.width() * permutation.height();
+                self.stats.domain_prunes += prunes;
+                self.notify(|observer, grid| observer.propagation_pruned(grid, &solver_move, prunes));
+                if !placed {
+                    // Propagation emptied a domain or left a piece nowhere to go, this placement is a dead end
+                    self.stats.placements_failed += 1;
+                    self.notify(|observer, grid| observer.piece_rejected(grid, &solver_move, Rejection::DeadEnd));
+                    self.pop_state();
+                    continue;
                 }
+                // println!("placed piece at {}, {}", x, y);
+                let depth = self.depth();
+                self.notify(|observer, grid| observer.piece_placed(grid, &solver_move, depth));
+                if !self.observers.is_empty()
+                    && let Some(solution) = self.current_solution()
+                {
+                    self.notify(|observer, _| observer.solution_found(&solution));
+                }
+                placed_piece = true;
+                break;
             }
+            self.stats.placements_rejected += 1;
+            self.notify(|observer, grid| observer.piece_rejected(grid, &solver_move, Rejection::DoesNotFit));
+        }
 
         // println!("We didn't find any matching piece for domain: {:?}", domain)
 
         if !placed_piece {
-            if self.grid_stack.len() == 1 {
+            if self.depth() == 0 {
                 return Err(());
             }
-            self.pop_state();
+            self.backtrack();
             // Couldn't place a piece, we need to backtrack
             // println!("Failed to place any piece, backtracking...")
         }
         Ok(())
     }
 
+    fn backtrack(&mut self) {
+        self.stats.backtracks += 1;
+        self.pop_state();
+        let depth = self.depth();
+        self.notify(|observer, grid| observer.backtracked(grid, depth));
+    }
+
+    fn notify(&mut self, mut event: impl FnMut(&mut dyn SolverObserver, &Grid)) {
+        for observer in &mut self.observers {
+            event(observer.as_mut(), &self.grid);
+        }
+    }
+
+    /// Subscribes `observer` to the events of the search, see [`SolverObserver`]
+    pub fn add_observer(&mut self, observer: Box<dyn SolverObserver>) {
+        self.observers.push(observer);
+    }
+
     pub fn current_grid(&self) -> &Grid {
-        self.grid_stack.last().unwrap()
+        &self.grid
     }
 
     pub fn current_grid_mut(&mut self) -> &mut Grid {
-        self.grid_stack.last_mut().unwrap()
+        &mut self.grid
+    }
+
+    /// How many pieces are placed on the current search path
+    pub fn depth(&self) -> usize {
+        self.checkpoints.len()
     }
 
     pub fn push_state(&mut self) {
-        self.grid_stack.push(self.current_grid().clone());
+        self.checkpoints.push(self.grid.checkpoint());
         self.tried_branches.push(vec![]);
+        self.branch_cells.push(None);
+        self.stats.max_depth = self.stats.max_depth.max(self.depth());
         // println!("Pushed state with solved: {}/{}", get_piece_domain().len() - self.current_grid().pieces_left.len(), get_piece_domain().len());
     }
 
     pub fn pop_state(&mut self) {
-        self.grid_stack.pop().unwrap();
+        let checkpoint = self.checkpoints.pop().unwrap();
+        self.grid.undo_to(checkpoint);
         self.tried_branches.pop().unwrap();
+        self.branch_cells.pop().unwrap();
+    }
+
+    /// Undoes the last placement the search made without counting it as tried, so the next step can place it again.
+    /// Deterministic heuristics do, random ones (see [`SolverConfig::randomized`]) draw a new order and may pick another
+    /// placement, the random number generator isn't rewound. Returns false if nothing has been placed yet.
+    pub fn step_back(&mut self) -> bool {
+        if self.depth() == 0 {
+            return false;
+        }
+        self.pop_state();
+        self.tried_branches.last_mut().unwrap().pop();
+        let depth = self.depth();
+        self.notify(|observer, grid| observer.backtracked(grid, depth));
+        true
+    }
+
+    /// Steps back, or searches forward, until `depth` pieces are placed. Returns false if the search ended before
+    /// getting that deep, or the limit that stopped it first. Calling it again after a limit carries on the seek.
+    pub fn seek_depth(&mut self, depth: usize, limits: &SolveLimits) -> Result<bool, Limit> {
+        while self.depth() > depth {
+            self.step_back();
+        }
+        let mut steps = 0;
+        while self.depth() < depth {
+            if let Some(limit) = limits.reached(steps) {
+                return Err(limit);
+            }
+            steps += 1;
+            if self.step_propagate().is_err() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn config(&self) -> &SolverConfig {
+        &self.config
     }
 
-    pub fn solve(&mut self) -> bool {
-        while let Ok(_) = self.step_propagate() {}
-        self.current_grid().pieces_left.len() == 0
+    /// The pieces the search started with
+    pub fn pieces(&self) -> &[&'static dyn PieceOps] {
+        &self.pieces
+    }
+
+    /// The seed the search was started with, reproduces the run when passed in a [`SolverConfig`]
+    pub fn seed(&self) -> u64 {
+        self.config.seed
+    }
+
+    pub fn stats(&self) -> &SolverStats {
+        &self.stats
+    }
+
+    /// Throws away the search so far and starts over from the empty board with the random choices reseeded
+    pub fn restart(&mut self, seed: u64) {
+        if self.depth() > 0 {
+            while self.depth() > 0 {
+                self.pop_state();
+            }
+            self.notify(|observer, grid| observer.backtracked(grid, 0));
+        }
+        self.tried_branches = vec![vec![]];
+        self.branch_cells = vec![None];
+        self.config.seed = seed;
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    /// Replaces the heuristic that picks which cell to branch on, [`MinimumRemainingValues`] by default
+    pub fn set_cell_selector(&mut self, cell_selector: Box<dyn CellSelector>) {
+        self.cell_selector = cell_selector;
+    }
+
+    /// Replaces the heuristic that orders the placements tried on each cell, [`PieceDomainOrder`] by default
+    pub fn set_piece_orderer(&mut self, piece_orderer: Box<dyn PieceOrderer>) {
+        self.piece_orderer = piece_orderer;
+    }
+
+    /// Only lets one piece be placed in as many rotations (mirrored or not) as needed to reach every solution up to
+    /// rotating the whole board, so rotated copies of a solution are mostly never searched.
+    /// Has to be called before the search starts.
+    pub fn break_symmetry(&mut self) {
+        debug_assert_eq!(self.depth(), 0, "Symmetry can only be broken before searching");
+        let grid = self.current_grid();
+        let allowed_rotations = if grid.width == grid.height { vec![PieceRotation::CCW0] } else { vec![PieceRotation::CCW0, PieceRotation::CCW90] };
+
+        // Prefer a piece that looks different in every rotation, it prunes the most
+        let has_distinct_rotations = |piece: &&&dyn PieceOps| self.placement_table.orientations.of(piece.piece_id()).iter().filter(|oriented| !oriented.orientation.is_mirrored()).count() == 4;
+        let restricted_piece = grid.pieces_left.iter().find(has_distinct_rotations).or(grid.pieces_left.first());
+        self.symmetry_restriction = restricted_piece.map(|piece| (piece.piece_id(), allowed_rotations));
+    }
+
+    /// Checks the puzzle can be solved at all before spending any time searching, see [`check_puzzle`]
+    pub fn validate_input(&self) -> Result<(), SolveError> {
+        check_puzzle(self.grid.width, self.grid.height, &self.pieces)
+    }
+
+    pub fn solve(&mut self) -> Result<Solution, SolveError> {
+        self.validate_input()?;
+        if self.search() { Ok(self.current_solution().unwrap()) } else { Err(SolveError::Exhausted) }
+    }
+
+    /// Like [`SolverState::solve`], but stops early once any of `limits` is reached.
+    /// The solver keeps its state when it stops, so calling this again carries on where it left off.
+    pub fn solve_with_limits(&mut self, limits: &SolveLimits) -> SolveOutcome {
+        if let Err(err) = self.validate_input() {
+            return SolveOutcome::Failed(err);
+        }
+        let mut steps = 0;
+        loop {
+            if let Some(limit) = limits.reached(steps) {
+                return SolveOutcome::LimitReached { limit, steps, partial: self.current_placements(), grid: self.grid.snapshot() };
+            }
+            steps += 1;
+            if self.step_propagate().is_err() {
+                break;
+            }
+        }
+        match self.current_solution() {
+            Some(solution) => SolveOutcome::Solved(solution),
+            None => SolveOutcome::Failed(SolveError::Exhausted),
+        }
+    }
+
+    fn search(&mut self) -> bool {
+        while self.step_propagate().is_ok() {}
+        self.current_grid().is_solved()
+    }
+
+    fn advance_to_next_solution(&mut self) -> bool {
+        if self.validate_input().is_err() {
+            return false;
+        }
+        if self.current_grid().is_solved() {
+            if self.depth() == 0 {
+                return false;
+            }
+            self.pop_state();
+        }
+        self.search()
+    }
+
+    /// The solution the search is currently sitting on, if any
+    pub fn current_solution(&self) -> Option<Solution> {
+        if !self.current_grid().is_solved() {
+            return None;
+        }
+        Some(Solution { placements: self.current_placements(), grid: self.grid.snapshot(), seed: Some(self.config.seed) })
+    }
+
+    /// Every piece placed on the current search path, in the order it was placed
+    pub fn current_placements(&self) -> Vec<SolverMove> {
+        // The last move tried on each level is the one that led to the next level
+        self.fixed_moves.iter().cloned().chain(self.tried_branches[..self.depth()].iter().map(|tried| tried.last().unwrap().clone())).collect()
+    }
+
+    /// Continues the search until the next solution is found.
+    /// If the current grid is already a solution, the search backtracks out of it first,
+    /// so calling this repeatedly visits every solution exactly once.
+    pub fn next_solution(&mut self) -> Option<Solution> {
+        if self.advance_to_next_solution() { self.current_solution() } else { None }
+    }
+
+    /// Iterates over every solution, see [`SolverState::next_solution`]
+    pub fn solutions(&mut self) -> Solutions<'_> {
+        Solutions { solver: self }
+    }
+
+    /// Counts every solution without keeping any of them around
+    pub fn count_solutions(&mut self) -> usize {
+        let mut count = 0;
+        while self.advance_to_next_solution() {
+            count += 1;
+        }
+        count
+    }
+
+    /// Counts every solution, and how many are left once solutions that are rotated
+    /// (or with `include_reflections`, mirrored) copies of each other are merged.
+    /// With [`SolverState::break_symmetry`] the raw count still includes the rotated copies the search skipped.
+    pub fn count_unique_solutions(&mut self, include_reflections: bool) -> SolutionCounts {
+        let mut tally = SolutionTally::new(self.current_grid().width, self.current_grid().height, include_reflections, self.symmetry_restriction.is_some());
+        while self.advance_to_next_solution() {
+            tally.add(&self.current_solution().unwrap());
+        }
+        tally.counts()
+    }
+}
+
+pub struct Solutions<'solver> {
+    solver: &'solver mut SolverState,
+}
+
+impl Iterator for Solutions<'_> {
+    type Item = Solution;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.solver.next_solution()
     }
 }
 
@@ -472,5 +1216,61 @@ pub enum PieceRotation {
 }
 
 impl PieceRotation {
-    const ROTATIONS: [PieceRotation; 4] = [PieceRotation::CCW0, PieceRotation::CCW90, PieceRotation::CCW180, PieceRotation::CCW270];
+    pub const ROTATIONS: [PieceRotation; 4] = [PieceRotation::CCW0, PieceRotation::CCW90, PieceRotation::CCW180, PieceRotation::CCW270];
+}
+
+/// The 8 ways a piece can be laid down, a mirrored orientation is flipped horizontally before it is rotated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PieceOrientation {
+    CCW0,
+    CCW90,
+    CCW180,
+    CCW270,
+    MirroredCCW0,
+    MirroredCCW90,
+    MirroredCCW180,
+    MirroredCCW270,
+}
+
+impl PieceOrientation {
+    /// The unmirrored orientations come first
+    pub const ORIENTATIONS: [PieceOrientation; 8] = [
+        PieceOrientation::CCW0,
+        PieceOrientation::CCW90,
+        PieceOrientation::CCW180,
+        PieceOrientation::CCW270,
+        PieceOrientation::MirroredCCW0,
+        PieceOrientation::MirroredCCW90,
+        PieceOrientation::MirroredCCW180,
+        PieceOrientation::MirroredCCW270,
+    ];
+
+    /// Every orientation the piece may be placed in, the mirrored ones only if it can be flipped
+    pub fn allowed_for(piece: &dyn PieceOps) -> &'static [PieceOrientation] {
+        if piece.can_flip() { &PieceOrientation::ORIENTATIONS } else { &PieceOrientation::ORIENTATIONS[..4] }
+    }
+
+    pub const fn rotation(&self) -> PieceRotation {
+        match self {
+            PieceOrientation::CCW0 | PieceOrientation::MirroredCCW0 => PieceRotation::CCW0,
+            PieceOrientation::CCW90 | PieceOrientation::MirroredCCW90 => PieceRotation::CCW90,
+            PieceOrientation::CCW180 | PieceOrientation::MirroredCCW180 => PieceRotation::CCW180,
+            PieceOrientation::CCW270 | PieceOrientation::MirroredCCW270 => PieceRotation::CCW270,
+        }
+    }
+
+    pub const fn is_mirrored(&self) -> bool {
+        matches!(self, PieceOrientation::MirroredCCW0 | PieceOrientation::MirroredCCW90 | PieceOrientation::MirroredCCW180 | PieceOrientation::MirroredCCW270)
+    }
+}
+
+impl From<PieceRotation> for PieceOrientation {
+    fn from(rotation: PieceRotation) -> Self {
+        match rotation {
+            PieceRotation::CCW0 => PieceOrientation::CCW0,
+            PieceRotation::CCW90 => PieceOrientation::CCW90,
+            PieceRotation::CCW180 => PieceOrientation::CCW180,
+            PieceRotation::CCW270 => PieceOrientation::CCW270,
+        }
+    }
 }
@@ -0,0 +1,281 @@
+//! Solving the puzzle as an exact cover problem with Dancing Links (Knuth's Algorithm X), as an
+//! alternative to the wave function collapse search in [`SolverState`](crate::constraint_solver::SolverState).
+//!
+//! Every legal placement of a piece is a row. It covers one primary column for the piece and one for each
+//! cell under it, so a solution uses every piece once and covers every cell once. Edges are secondary columns,
+//! which may be covered at most once: for every edge between two cells there is a column per connection type,
+//! the cell on the left/top side of the edge covers the columns of every connection it *doesn't* have and the
+//! cell on the right/bottom side covers the column of the connection it has, so two placements only conflict
+//! when their connections differ.
+
+use crate::constraint_solver::{Grid, SolveError, Solution, SolverMove};
+use crate::piece::{ConnectionType, PieceOps, get_piece_domain};
+use crate::symmetry::{SolutionCounts, SolutionTally};
+use crate::validation::check_puzzle;
+
+const CONNECTION_TYPES: [ConnectionType; 3] = [ConnectionType::NoConnection, ConnectionType::Straight, ConnectionType::Double];
+
+#[derive(Debug)]
+pub struct ExactCoverSolver {
+    /// The empty board, solutions are built on a copy of it
+    grid: Grid,
+    pieces: Vec<&'static dyn PieceOps>,
+    links: DancingLinks,
+    /// The placement (in the grid's placement table) each row stands for
+    row_placements: Vec<usize>,
+}
+
+impl ExactCoverSolver {
+    pub fn new(width: usize, height: usize) -> ExactCoverSolver {
+        ExactCoverSolver::with_pieces(width, height, get_piece_domain())
+    }
+
+    pub fn with_pieces(width: usize, height: usize, pieces: &[&'static dyn PieceOps]) -> ExactCoverSolver {
+        let grid = Grid::with_pieces(width, height, pieces);
+        let placement_table = grid.placement_table();
+
+        let cell_column = |x: usize, y: usize| pieces.len() + y * width + x;
+        let edge_columns_start = pieces.len() + width * height;
+        let horizontal_edge = |x: usize, y: usize| edge_columns_start + (y * width.saturating_sub(1) + x) * CONNECTION_TYPES.len();
+        let vertical_edge = |x: usize, y: usize| edge_columns_start + (height * width.saturating_sub(1) + y * width + x) * CONNECTION_TYPES.len();
+        let edge_column_count = (height * width.saturating_sub(1) + height.saturating_sub(1) * width) * CONNECTION_TYPES.len();
+
+        let mut links = DancingLinks::new(edge_columns_start, edge_column_count);
+        let mut row_placements = vec![];
+        // Placements that already clash with the border (or can't be completed at all) were pruned by the grid
+        for (index, placement) in placement_table.placements.iter().enumerate().filter(|(index, _)| grid.is_placement_live(*index)) {
+            let piece = placement_table.oriented_piece(placement);
+            let inside = |x: usize, y: usize| (placement.x..placement.x + piece.width()).contains(&x) && (placement.y..placement.y + piece.height()).contains(&y);
+            let not = |connection: ConnectionType| CONNECTION_TYPES.iter().enumerate().filter(move |(_, other)| **other != connection).map(|(offset, _)| offset);
+
+            let mut columns = vec![placement.piece_index];
+            for &(x, y, cell) in &placement.cells {
+                columns.push(cell_column(x, y));
+                if x + 1 < width && !inside(x + 1, y) {
+                    columns.extend(not(cell.right).map(|offset| horizontal_edge(x, y) + offset));
+                }
+                if x > 0 && !inside(x - 1, y) {
+                    columns.push(horizontal_edge(x - 1, y) + cell.left as usize);
+                }
+                if y + 1 < height && !inside(x, y + 1) {
+                    columns.extend(not(cell.bottom).map(|offset| vertical_edge(x, y) + offset));
+                }
+                if y > 0 && !inside(x, y - 1) {
+                    columns.push(vertical_edge(x, y - 1) + cell.top as usize);
+                }
+            }
+            links.add_row(row_placements.len(), &columns);
+            row_placements.push(index);
+        }
+
+        ExactCoverSolver { grid, pieces: pieces.to_vec(), links, row_placements }
+    }
+
+    pub fn solve(&mut self) -> Result<Solution, SolveError> {
+        check_puzzle(self.grid.width, self.grid.height, &self.pieces)?;
+        let mut solution = None;
+        self.for_each_solution(|found| {
+            solution = Some(found);
+            false
+        });
+        solution.ok_or(SolveError::Exhausted)
+    }
+
+    /// Like [`SolverState::count_solutions`](crate::constraint_solver::SolverState::count_solutions)
+    pub fn count_solutions(&mut self) -> usize {
+        if check_puzzle(self.grid.width, self.grid.height, &self.pieces).is_err() {
+            return 0;
+        }
+        let mut count = 0;
+        self.links.search(&mut vec![], &mut |_| {
+            count += 1;
+            true
+        });
+        count
+    }
+
+    /// Like [`SolverState::count_unique_solutions`](crate::constraint_solver::SolverState::count_unique_solutions)
+    pub fn count_unique_solutions(&mut self, include_reflections: bool) -> SolutionCounts {
+        if check_puzzle(self.grid.width, self.grid.height, &self.pieces).is_err() {
+            return SolutionCounts { raw: 0, unique: 0 };
+        }
+        let mut tally = SolutionTally::new(self.grid.width, self.grid.height, include_reflections, false);
+        self.for_each_solution(|solution| {
+            tally.add(&solution);
+            true
+        });
+        tally.counts()
+    }
+
+    /// Calls `on_solution` with every solution until it returns false
+    fn for_each_solution(&mut self, mut on_solution: impl FnMut(Solution) -> bool) {
+        let ExactCoverSolver { grid, links, row_placements, .. } = self;
+        links.search(&mut vec![], &mut |rows| {
+            let placement_table = grid.placement_table();
+            let mut solution_grid = grid.snapshot();
+            let mut placements = vec![];
+            for &row in rows {
+                let placement = &placement_table.placements[row_placements[row]];
+                solution_grid.place_piece_unchecked(placement_table.oriented_piece(placement), placement.x, placement.y);
+                placements.push(SolverMove { piece_id: placement.piece_id, orientation: placement.orientation, x: placement.x, y: placement.y });
+            }
+            debug_assert!(solution_grid.is_solved());
+            on_solution(Solution { placements, grid: solution_grid, seed: None })
+        });
+    }
+}
+
+/// The sparse 0/1 matrix as circular doubly linked lists of its ones, node 0 is the root and nodes
+/// `1..=columns` are the column headers. Only primary columns are linked into the root's list.
+#[derive(Debug)]
+struct DancingLinks {
+    left: Vec<usize>,
+    right: Vec<usize>,
+    up: Vec<usize>,
+    down: Vec<usize>,
+    /// Header node of each node's column
+    column: Vec<usize>,
+    /// Row of each node, meaningless for the root and headers
+    row: Vec<usize>,
+    /// How many nodes are left in each column, indexed by header node
+    size: Vec<usize>,
+}
+
+impl DancingLinks {
+    fn new(primary_columns: usize, secondary_columns: usize) -> DancingLinks {
+        let header_count = primary_columns + secondary_columns + 1;
+        let mut links = DancingLinks {
+            left: (0..header_count).collect(),
+            right: (0..header_count).collect(),
+            up: (0..header_count).collect(),
+            down: (0..header_count).collect(),
+            column: (0..header_count).collect(),
+            row: vec![0; header_count],
+            size: vec![0; header_count],
+        };
+        for header in 0..=primary_columns {
+            links.left[header] = if header == 0 { primary_columns } else { header - 1 };
+            links.right[header] = if header == primary_columns { 0 } else { header + 1 };
+        }
+        links
+    }
+
+    fn add_row(&mut self, row: usize, columns: &[usize]) {
+        let first = self.left.len();
+        for (i, column) in columns.iter().enumerate() {
+            let header = column + 1;
+            let node = first + i;
+            self.left.push(if i == 0 { first + columns.len() - 1 } else { node - 1 });
+            self.right.push(if i + 1 == columns.len() { first } else { node + 1 });
+            self.up.push(self.up[header]);
+            self.down.push(header);
+            self.column.push(header);
+            self.row.push(row);
+            let last = self.up[header];
+            self.down[last] = node;
+            self.up[header] = node;
+            self.size[header] += 1;
+        }
+    }
+
+    fn cover(&mut self, header: usize) {
+        let (left, right) = (self.left[header], self.right[header]);
+        self.right[left] = right;
+        self.left[right] = left;
+        let mut row_node = self.down[header];
+        while row_node != header {
+            let mut node = self.right[row_node];
+            while node != row_node {
+                let (up, down) = (self.up[node], self.down[node]);
+                self.down[up] = down;
+                self.up[down] = up;
+                self.size[self.column[node]] -= 1;
+                node = self.right[node];
+            }
+            row_node = self.down[row_node];
+        }
+    }
+
+    fn uncover(&mut self, header: usize) {
+        let mut row_node = self.up[header];
+        while row_node != header {
+            let mut node = self.left[row_node];
+            while node != row_node {
+                let (up, down) = (self.up[node], self.down[node]);
+                self.down[up] = node;
+                self.up[down] = node;
+                self.size[self.column[node]] += 1;
+                node = self.left[node];
+            }
+            row_node = self.up[row_node];
+        }
+        let (left, right) = (self.left[header], self.right[header]);
+        self.right[left] = header;
+        self.left[right] = header;
+    }
+
+    /// Algorithm X, always branching on the primary column with the fewest rows left.
+    /// Calls `on_solution` with the rows of every solution, returns false once it returned false.
+    fn search(&mut self, rows: &mut Vec<usize>, on_solution: &mut dyn FnMut(&[usize]) -> bool) -> bool {
+        if self.right[0] == 0 {
+            return on_solution(rows);
+        }
+        let mut header = self.right[0];
+        let mut candidate = self.right[header];
+        while candidate != 0 {
+            if self.size[candidate] < self.size[header] {
+                header = candidate;
+            }
+            candidate = self.right[candidate];
+        }
+        if self.size[header] == 0 {
+            return true;
+        }
+
+        self.cover(header);
+        let mut row_node = self.down[header];
+        let mut keep_going = true;
+        while keep_going && row_node != header {
+            rows.push(self.row[row_node]);
+            let mut node = self.right[row_node];
+            while node != row_node {
+                self.cover(self.column[node]);
+                node = self.right[node];
+            }
+            keep_going = self.search(rows, on_solution);
+            let mut node = self.left[row_node];
+            while node != row_node {
+                self.uncover(self.column[node]);
+                node = self.left[node];
+            }
+            rows.pop();
+            row_node = self.down[row_node];
+        }
+        self.uncover(header);
+        keep_going
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraint_solver::SolverState;
+    use crate::parallel::ParallelSolver;
+
+    #[test]
+    fn backends_agree_on_the_default_set() {
+        let expected = SolutionCounts { raw: 4, unique: 1 };
+        assert_eq!(SolverState::new(6, 6).count_unique_solutions(true), expected);
+        assert_eq!(ExactCoverSolver::new(6, 6).count_unique_solutions(true), expected);
+        assert_eq!(ParallelSolver::new(6, 6, get_piece_domain()).with_threads(2).count_unique_solutions(true), expected);
+    }
+
+    #[test]
+    fn broken_symmetry_still_counts_every_rotation() {
+        let expected = SolutionCounts { raw: 4, unique: 1 };
+        let mut solver = SolverState::new(6, 6);
+        solver.break_symmetry();
+        assert_eq!(solver.count_unique_solutions(false), expected);
+        assert_eq!(ParallelSolver::new(6, 6, get_piece_domain()).with_threads(2).with_broken_symmetry().count_unique_solutions(false), expected);
+    }
+}
@@ -0,0 +1,228 @@
+//! Strategies for steering the search in [`SolverState`](crate::constraint_solver::SolverState),
+//! so different heuristics can be benchmarked against each other on the same boards.
+
+use crate::constraint_solver::CellSolveState::{Solved, Unsolved};
+use crate::constraint_solver::{Direction, Domain, Grid};
+use crate::piece::CELL_COUNT;
+use rand::rngs::SmallRng;
+use rand::seq::{IndexedRandom, SliceRandom};
+use std::fmt::Debug;
+
+/// Decides which unsolved cell the search branches on next
+pub trait CellSelector: Debug + Send + Sync {
+    /// Called once before the cells of `grid` are scored
+    fn prepare(&mut self, _grid: &Grid) {}
+
+    /// Lower scores are branched on first
+    fn score(&self, grid: &Grid, x: usize, y: usize, domain: Domain) -> f64;
+
+    /// Picks the lowest scoring unsolved cell, ties go to the first one in scan order.
+    /// Returns `None` once every cell is solved.
+    fn select(&mut self, grid: &Grid, _rng: &mut SmallRng) -> Option<(usize, usize)> {
+        self.prepare(grid);
+        unsolved_cells(grid).map(|(x, y, domain)| (self.score(grid, x, y, domain), x, y)).min_by(|(score1, ..), (score2, ..)| score1.total_cmp(score2)).map(|(_, x, y)| (x, y))
+    }
+}
+
+fn unsolved_cells(grid: &Grid) -> impl Iterator<Item = (usize, usize, Domain)> + '_ {
+    grid.grid.iter().enumerate().flat_map(|(y, row)| row.iter().enumerate().filter_map(move |(x, cell_state)| if let Unsolved(domain) = cell_state { Some((x, y, *domain)) } else { None }))
+}
+
+/// The cell with the fewest possible cells left (minimum remaining values)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinimumRemainingValues;
+
+impl CellSelector for MinimumRemainingValues {
+    fn score(&self, _grid: &Grid, _x: usize, _y: usize, domain: Domain) -> f64 {
+        domain.len() as f64
+    }
+}
+
+/// The cell with the lowest Shannon entropy, where each possible cell is weighted by how often
+/// it appears on the pieces that are left, so cells only rare pieces can fill are picked early
+#[derive(Debug, Clone, Default)]
+pub struct WeightedEntropy {
+    weights: Vec<f64>,
+}
+
+impl CellSelector for WeightedEntropy {
+    fn prepare(&mut self, grid: &Grid) {
+        self.weights = vec![0.0; CELL_COUNT];
+        let orientations = &grid.placement_table().orientations;
+        for piece in &grid.pieces_left {
+            for oriented in orientations.of(piece.piece_id()) {
+                for cell in oriented.piece.cells_flat() {
+                    self.weights[cell.id()] += 1.0;
+                }
+            }
+        }
+    }
+
+    fn score(&self, _grid: &Grid, _x: usize, _y: usize, domain: Domain) -> f64 {
+        let weights = domain.iter().map(|cell| self.weights[cell.id()]).filter(|weight| *weight > 0.0).collect::<Vec<_>>();
+        let total: f64 = weights.iter().sum();
+        if total == 0.0 {
+            // No piece left can fill the cell, branching on it fails straight away
+            return f64::NEG_INFINITY;
+        }
+        total.ln() - weights.iter().map(|weight| weight * weight.ln()).sum::<f64>() / total
+    }
+}
+
+/// The cell with the most solved neighbours (the border counts as one), ties go to the smallest domain
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MostConstrained;
+
+impl CellSelector for MostConstrained {
+    fn score(&self, grid: &Grid, x: usize, y: usize, domain: Domain) -> f64 {
+        let constrained_sides = Direction::ALL.iter().filter(|direction| grid.neighbor_position(x, y, **direction).is_none_or(|(nx, ny)| matches!(grid.grid[ny][nx], Solved(_)))).count();
+        domain.len() as f64 / (CELL_COUNT + 1) as f64 - constrained_sides as f64
+    }
+}
+
+/// The first unsolved cell, reading the board left to right and top to bottom
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScanOrder;
+
+impl CellSelector for ScanOrder {
+    fn score(&self, _grid: &Grid, _x: usize, _y: usize, _domain: Domain) -> f64 {
+        0.0
+    }
+}
+
+/// Scores cells like `S`, but breaks ties at random instead of by scan order
+#[derive(Debug, Clone, Default)]
+pub struct RandomTieBreak<S>(pub S);
+
+impl<S: CellSelector> CellSelector for RandomTieBreak<S> {
+    fn prepare(&mut self, grid: &Grid) {
+        self.0.prepare(grid);
+    }
+
+    fn score(&self, grid: &Grid, x: usize, y: usize, domain: Domain) -> f64 {
+        self.0.score(grid, x, y, domain)
+    }
+
+    fn select(&mut self, grid: &Grid, rng: &mut SmallRng) -> Option<(usize, usize)> {
+        self.prepare(grid);
+        let scored = unsolved_cells(grid).map(|(x, y, domain)| (self.score(grid, x, y, domain), x, y)).collect::<Vec<_>>();
+        let best = scored.iter().map(|(score, ..)| *score).min_by(f64::total_cmp)?;
+        let ties = scored.into_iter().filter(|(score, ..)| *score == best).collect::<Vec<_>>();
+        ties.choose(rng).map(|(_, x, y)| (*x, *y))
+    }
+}
+
+impl CellSelector for Box<dyn CellSelector> {
+    fn prepare(&mut self, grid: &Grid) {
+        self.as_mut().prepare(grid);
+    }
+
+    fn score(&self, grid: &Grid, x: usize, y: usize, domain: Domain) -> f64 {
+        self.as_ref().score(grid, x, y, domain)
+    }
+
+    fn select(&mut self, grid: &Grid, rng: &mut SmallRng) -> Option<(usize, usize)> {
+        self.as_mut().select(grid, rng)
+    }
+}
+
+/// Decides in which order the placements covering the chosen cell are tried
+pub trait PieceOrderer: Debug + Send + Sync {
+    /// Reorders `candidates`, indices into the grid's placement table of the placements that are still legal
+    fn order(&mut self, grid: &Grid, candidates: &mut [usize], rng: &mut SmallRng);
+}
+
+/// Pieces in the order of the piece domain, each in the order of [`PieceOrientation::ORIENTATIONS`](crate::constraint_solver::PieceOrientation::ORIENTATIONS)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PieceDomainOrder;
+
+impl PieceOrderer for PieceDomainOrder {
+    fn order(&mut self, _grid: &Grid, _candidates: &mut [usize], _rng: &mut SmallRng) {}
+}
+
+/// Pieces covering the most cells first, they are the hardest to fit in later
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFirst;
+
+impl PieceOrderer for LargestFirst {
+    fn order(&mut self, grid: &Grid, candidates: &mut [usize], _rng: &mut SmallRng) {
+        let placements = &grid.placement_table().placements;
+        candidates.sort_by_key(|index| std::cmp::Reverse(placements[*index].cells.len()));
+    }
+}
+
+/// Pieces with the fewest legal placements left anywhere on the board first
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FewestPlacementsFirst;
+
+impl PieceOrderer for FewestPlacementsFirst {
+    fn order(&mut self, grid: &Grid, candidates: &mut [usize], _rng: &mut SmallRng) {
+        let placements = &grid.placement_table().placements;
+        candidates.sort_by_key(|index| grid.live_placement_count(placements[*index].piece_index));
+    }
+}
+
+/// A random order, drawn from the solver's seeded random number generator
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomOrder;
+
+impl PieceOrderer for RandomOrder {
+    fn order(&mut self, _grid: &Grid, candidates: &mut [usize], rng: &mut SmallRng) {
+        candidates.shuffle(rng);
+    }
+}
+
+/// Orders like `O`, but placements `O` ranks the same end up in a random order instead of the placement table's.
+/// Relies on `O` sorting stably, which every orderer here does.
+#[derive(Debug, Clone, Default)]
+pub struct RandomTieOrder<O>(pub O);
+
+impl<O: PieceOrderer> PieceOrderer for RandomTieOrder<O> {
+    fn order(&mut self, grid: &Grid, candidates: &mut [usize], rng: &mut SmallRng) {
+        candidates.shuffle(rng);
+        self.0.order(grid, candidates, rng);
+    }
+}
+
+impl PieceOrderer for Box<dyn PieceOrderer> {
+    fn order(&mut self, grid: &Grid, candidates: &mut [usize], rng: &mut SmallRng) {
+        self.as_mut().order(grid, candidates, rng);
+    }
+}
+
+/// Placements that overlap the fewest other legal placements first (least constraining value),
+/// so the pieces left keep as many options as possible
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LeastConstraining;
+
+impl PieceOrderer for LeastConstraining {
+    fn order(&mut self, grid: &Grid, candidates: &mut [usize], _rng: &mut SmallRng) {
+        let placements = &grid.placement_table().placements;
+        candidates.sort_by_cached_key(|index| placements[*index].cells.iter().map(|(x, y, _)| grid.coverage(*x, *y)).sum::<usize>());
+    }
+}
+
+/// Looks up a built-in cell selector by the name used on the command line
+pub fn cell_selector_by_name(name: &str) -> Option<Box<dyn CellSelector>> {
+    Some(match name {
+        "mrv" => Box::new(MinimumRemainingValues),
+        "entropy" => Box::new(WeightedEntropy::default()),
+        "most-constrained" => Box::new(MostConstrained),
+        "scan" => Box::new(ScanOrder),
+        "random-mrv" => Box::new(RandomTieBreak(MinimumRemainingValues)),
+        "random-entropy" => Box::new(RandomTieBreak(WeightedEntropy::default())),
+        _ => return None,
+    })
+}
+
+/// Looks up a built-in piece orderer by the name used on the command line
+pub fn piece_orderer_by_name(name: &str) -> Option<Box<dyn PieceOrderer>> {
+    Some(match name {
+        "domain" => Box::new(PieceDomainOrder),
+        "largest" => Box::new(LargestFirst),
+        "fewest-placements" => Box::new(FewestPlacementsFirst),
+        "random" => Box::new(RandomOrder),
+        "least-constraining" => Box::new(LeastConstraining),
+        _ => return None,
+    })
+}
@@ -1,2 +1,13 @@
 pub mod constraint_solver;
-pub mod piece;
\ No newline at end of file
+pub mod exact_cover;
+pub mod heuristics;
+pub mod observer;
+pub mod parallel;
+pub mod piece;
+pub mod piece_set;
+pub mod placements;
+pub mod restarts;
+pub mod sat;
+pub mod symmetry;
+pub mod trace;
+pub mod validation;
@@ -1,12 +1,23 @@
 pub mod constraint_solver;
+pub mod heuristics;
+pub mod observer;
+pub mod parallel;
 pub mod piece;
+pub mod placements;
+pub mod restarts;
+mod solver_thread;
+pub mod symmetry;
+pub mod trace;
+pub mod validation;
 
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use crate::constraint_solver::CellSolveState::{Solved, Unsolved};
-use crate::constraint_solver::{Grid, SolverState};
-use crate::piece::VisualCell;
+use crate::constraint_solver::{Grid, SolverState, PUZZLE_HEIGHT, PUZZLE_WIDTH};
+use crate::piece::{get_piece_domain, VisualCell};
+use crate::solver_thread::{Command, SolverThread};
+use crate::trace::{Trace, TraceReplayer};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::{Color, PixelFormatEnum};
@@ -28,15 +39,14 @@ fn load_image_as_texture<P: AsRef<Path>>(binding: &'_ TextureCreator<WindowConte
     Ok(texture)
 }
 
-fn run_sync(mut canvas: WindowCanvas, mut texture_cache: TextureCache, mut event_pump: EventPump) {
-    let mut solver = SolverState::new();
+// Space/Right steps forward, Backspace/Left steps back, A toggles auto mode, B toggles pausing on backtracks,
+// +/- doubles or halves the steps per frame, U toggles stepping as fast as possible,
+// typing a number and pressing Return jumps to that depth
+fn run_threaded(mut canvas: WindowCanvas, mut texture_cache: TextureCache, mut event_pump: EventPump, snapshot_interval: Duration) {
+    let solver = SolverState::new(PUZZLE_WIDTH, PUZZLE_HEIGHT);
+    let solver_thread = SolverThread::spawn(solver, snapshot_interval);
 
-    canvas.clear();
-    draw_grid(&mut canvas, &mut texture_cache, solver.current_grid());
-    canvas.present();
-
-    let mut needs_redraw = false;
-    let mut auto_mode = false;
+    let mut target_depth: Option<usize> = None;
 
     let mut frames_in_last_second = 0;
     let mut last_frames_measure = Instant::now();
@@ -45,34 +55,40 @@ fn run_sync(mut canvas: WindowCanvas, mut texture_cache: TextureCache, mut event
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
-                Event::KeyDown { keycode,  .. } => {
-                    if keycode == Some(Keycode::Space) {
-                        auto_mode = false;
-                        let solver_result = solver.step_propagate();
-                        if solver_result.is_err() {
-                            println!("Failed to tick solver");
+                Event::KeyDown { keycode: Some(keycode), .. } => {
+                    let command = match keycode {
+                        Keycode::Space | Keycode::Right => Command::Step,
+                        Keycode::Backspace | Keycode::Left => Command::StepBack,
+                        Keycode::A => Command::ToggleAuto,
+                        Keycode::B => Command::TogglePauseOnBacktrack,
+                        Keycode::Equals | Keycode::Plus | Keycode::KpPlus => Command::Faster,
+                        Keycode::Minus | Keycode::KpMinus => Command::Slower,
+                        Keycode::U => Command::ToggleUnbounded,
+                        Keycode::Return => match target_depth.take() {
+                            Some(depth) => Command::SeekDepth(depth),
+                            None => continue,
+                        },
+                        _ => {
+                            if let Ok(digit) = keycode.name().parse::<usize>() {
+                                let depth = target_depth.unwrap_or(0) * 10 + digit;
+                                println!("Jump to depth: {}", depth);
+                                target_depth = Some(depth);
+                            }
+                            continue;
                         }
-                        needs_redraw = true;
-                    } else if keycode == Some (Keycode::A) {
-                        auto_mode = !auto_mode;
-                    }
+                    };
+                    solver_thread.send(command);
                 }
                 _ => {}
             }
         }
 
-        if auto_mode {
-            let solver_result = solver.step_propagate();
-            if solver_result.is_err() {
-                auto_mode = false;
+        if let Some(snapshot) = solver_thread.latest_snapshot(Duration::from_secs(1) / 60) {
+            if let Some(pause) = snapshot.pause {
+                println!("{:?} at depth {} after {} steps", pause, snapshot.depth, snapshot.steps);
             }
-            needs_redraw = true;
-        }
-
-        if needs_redraw {
-            needs_redraw = false;
             canvas.clear();
-            draw_grid(&mut canvas, &mut texture_cache, solver.current_grid());
+            draw_grid(&mut canvas, &mut texture_cache, &snapshot.grid);
             canvas.present();
         }
 
@@ -82,20 +98,69 @@ fn run_sync(mut canvas: WindowCanvas, mut texture_cache: TextureCache, mut event
             last_frames_measure = Instant::now();
             frames_in_last_second = 0;
         }
-        // std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+    }
+}
+
+// Scrubs through a recorded trace: Space/Right steps forward, Backspace/Left steps back, Home and End jump to the
+// start and the end, typing a number and pressing Return jumps to that event
+fn run_replay(mut canvas: WindowCanvas, mut texture_cache: TextureCache, mut event_pump: EventPump, mut replayer: TraceReplayer) {
+    canvas.clear();
+    draw_grid(&mut canvas, &mut texture_cache, replayer.grid());
+    canvas.present();
+
+    let mut target_position: Option<usize> = None;
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                Event::KeyDown { keycode: Some(keycode), .. } => {
+                    let position = match keycode {
+                        Keycode::Space | Keycode::Right => replayer.position() + 1,
+                        Keycode::Backspace | Keycode::Left => replayer.position().saturating_sub(1),
+                        Keycode::Home => 0,
+                        Keycode::End => replayer.len(),
+                        Keycode::Return => match target_position.take() {
+                            Some(position) => position,
+                            None => continue,
+                        },
+                        _ => {
+                            if let Ok(digit) = keycode.name().parse::<usize>() {
+                                let position = target_position.unwrap_or(0) * 10 + digit;
+                                println!("Jump to event: {}", position);
+                                target_position = Some(position);
+                            }
+                            continue;
+                        }
+                    };
+                    if let Err(err) = replayer.seek(position.min(replayer.len())) {
+                        println!("Failed to replay: {}", err);
+                    }
+                    match replayer.position().checked_sub(1).map(|last| &replayer.trace().events[last]) {
+                        Some(event) => println!("Event {}/{}: {:?}", replayer.position(), replayer.len(), event),
+                        None => println!("Event 0/{}", replayer.len()),
+                    }
+                    canvas.clear();
+                    draw_grid(&mut canvas, &mut texture_cache, replayer.grid());
+                    canvas.present();
+                }
+                _ => {}
+            }
+        }
+        std::thread::sleep(Duration::from_secs(1) / 60);
     }
 }
 
 fn draw_grid(canvas: &mut WindowCanvas, texture_cache: &mut TextureCache, grid: &Grid){
-    for y in 0..6 {
-        for x in 0..6 {
+    for y in 0..grid.height {
+        for x in 0..grid.width {
             if let Solved(cell) = grid.grid[y][x] {
                 // let (image_to_draw, angle) = image_for_cell(cell);
                 let visual_cell = grid.visual_grid[y][x];
                 let image_to_draw = texture_cache.get_texture_for_visual_cell(visual_cell);
                 let angle = visual_cell.angle() as f64;
                 let draw_rect = Rect::new((x * 32) as i32, (y * 32) as i32, 32, 32);
-                canvas.copy_ex(image_to_draw, None, draw_rect, 360.0 - angle, None, false, false).unwrap();
+                canvas.copy_ex(image_to_draw, None, draw_rect, 360.0 - angle, None, visual_cell.is_mirrored(), false).unwrap();
             }else if let Unsolved(domain) = &grid.grid[y][x] {
                 let mut len = domain.len();
                 let mut digits = vec![];
@@ -164,14 +229,23 @@ fn main() {
     let blit32_texture = texture_cache.get_or_load_texture("resources/blit32.png");
     blit32_texture.set_blend_mode(BlendMode::Blend);
 
-    canvas.set_logical_size(192, 192).unwrap();
+    let replayer = std::env::args().find_map(|arg| arg.strip_prefix("--replay=").map(str::to_owned)).map(|path| {
+        let trace = Trace::load(&path).unwrap_or_else(|err| panic!("Failed to load trace {}: {}", path, err));
+        TraceReplayer::new(trace, get_piece_domain()).unwrap_or_else(|err| panic!("Failed to replay trace {}: {}", path, err))
+    });
+    let (board_width, board_height) = replayer.as_ref().map_or((PUZZLE_WIDTH, PUZZLE_HEIGHT), |replayer| (replayer.trace().width, replayer.trace().height));
+    canvas.set_logical_size((board_width * 32) as u32, (board_height * 32) as u32).unwrap();
     canvas.set_draw_color(Color::RGB(25, 25, 25));
     canvas.clear();
     canvas.present();
 
 
+    let snapshots_per_second = std::env::args().find_map(|arg| arg.strip_prefix("--snapshots-per-second=").map(|rate| rate.parse::<u32>().expect("The snapshot rate must be a positive integer"))).unwrap_or(60);
     let event_pump = sdl_context.event_pump().unwrap();
-    run_sync(canvas, texture_cache, event_pump);
+    match replayer {
+        Some(replayer) => run_replay(canvas, texture_cache, event_pump, replayer),
+        None => run_threaded(canvas, texture_cache, event_pump, Duration::from_secs(1) / snapshots_per_second.max(1)),
+    }
 
 
 
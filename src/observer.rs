@@ -0,0 +1,33 @@
+//! Hooks into the search, so visualisers, loggers and statistics collectors can follow along
+//! without changing the solver loop. Register an observer with [`SolverState::add_observer`](crate::constraint_solver::SolverState::add_observer).
+
+use crate::constraint_solver::{Grid, Solution, SolverMove};
+use std::fmt::Debug;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    /// [`Grid::can_place_piece`] turned the placement down
+    DoesNotFit,
+    /// The placement fit, but propagation showed it can't lead to a solution
+    DeadEnd,
+}
+
+/// Every callback does nothing by default, so an observer only implements the events it cares about.
+/// `grid` is always the grid right after the event.
+pub trait SolverObserver: Debug + Send + Sync {
+    /// The search chose the cell at (`x`, `y`) to branch on
+    fn cell_selected(&mut self, _grid: &Grid, _x: usize, _y: usize) {}
+
+    /// A piece was placed, `depth` counts the pieces placed by the search including this one
+    fn piece_placed(&mut self, _grid: &Grid, _solver_move: &SolverMove, _depth: usize) {}
+
+    fn piece_rejected(&mut self, _grid: &Grid, _solver_move: &SolverMove, _rejection: Rejection) {}
+
+    /// Propagation after placing `solver_move` shrank `prunes` cell domains
+    fn propagation_pruned(&mut self, _grid: &Grid, _solver_move: &SolverMove, _prunes: usize) {}
+
+    /// The search gave up on a level and went back up to `depth`
+    fn backtracked(&mut self, _grid: &Grid, _depth: usize) {}
+
+    fn solution_found(&mut self, _solution: &Solution) {}
+}
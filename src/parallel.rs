@@ -0,0 +1,171 @@
+//! Searching on several threads by splitting the search tree at its first levels. Every path down to
+//! `split_depth` becomes a task with those placements fixed, and the tasks are shared out between the threads.
+
+use crate::constraint_solver::{SolveError, Solution, SolverConfig, SolverState};
+use crate::piece::PieceOps;
+use crate::symmetry::{SolutionCounts, SolutionTally};
+use crate::validation::check_puzzle;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+#[derive(Debug, Clone)]
+pub struct ParallelSolver {
+    width: usize,
+    height: usize,
+    pieces: Vec<&'static dyn PieceOps>,
+    config: SolverConfig,
+    threads: usize,
+    split_depth: usize,
+    break_symmetry: bool,
+}
+
+impl ParallelSolver {
+    /// Uses every available core and splits at the first level
+    pub fn new(width: usize, height: usize, pieces: &[&'static dyn PieceOps]) -> ParallelSolver {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        ParallelSolver { width, height, pieces: pieces.to_vec(), config: SolverConfig::default(), threads, split_depth: 1, break_symmetry: false }
+    }
+
+    pub fn with_config(mut self, config: SolverConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// How many levels of the search tree are split into tasks, more levels give more (and smaller) tasks
+    pub fn with_split_depth(mut self, split_depth: usize) -> Self {
+        self.split_depth = split_depth;
+        self
+    }
+
+    /// See [`SolverState::break_symmetry`]
+    pub fn with_broken_symmetry(mut self) -> Self {
+        self.break_symmetry = true;
+        self
+    }
+
+    fn root_solver(&self) -> SolverState {
+        let mut solver = SolverState::with_config(self.width, self.height, &self.pieces, self.config.clone());
+        if self.break_symmetry {
+            solver.break_symmetry();
+        }
+        solver
+    }
+
+    /// Every path from the root down to `split_depth`, as the placements along it.
+    /// Paths that solve the puzzle or reach a dead end before `split_depth` stop early.
+    fn split(&self, root: &SolverState) -> Vec<Vec<usize>> {
+        let mut tasks = vec![vec![]];
+        for _ in 0..self.split_depth {
+            let mut next_tasks = vec![];
+            for task in tasks {
+                // A placement further down the path that doesn't fit is a dead end, there's nothing to search
+                let Some(mut solver) = task_solver(root, &task) else {
+                    continue;
+                };
+                let branches = solver.root_branches();
+                if branches.is_empty() {
+                    next_tasks.push(task);
+                    continue;
+                }
+                for branch in branches {
+                    let mut next_task = task.clone();
+                    next_task.push(branch);
+                    next_tasks.push(next_task);
+                }
+            }
+            tasks = next_tasks;
+        }
+        tasks
+    }
+
+    /// Runs `search` on every task across the threads until it returns false for one of them
+    fn run_tasks(&self, search: impl Fn(SolverState, &AtomicBool) -> bool + Sync) {
+        let root = self.root_solver();
+        let tasks = self.split(&root);
+        let next_task = AtomicUsize::new(0);
+        let cancelled = AtomicBool::new(false);
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(tasks.len()) {
+                scope.spawn(|| {
+                    while !cancelled.load(Ordering::Relaxed) {
+                        let Some(task) = tasks.get(next_task.fetch_add(1, Ordering::Relaxed)) else {
+                            break;
+                        };
+                        let Some(solver) = task_solver(&root, task) else {
+                            continue;
+                        };
+                        if !search(solver, &cancelled) {
+                            cancelled.store(true, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Finds a solution, the other threads stop as soon as one thread found one
+    pub fn solve(&self) -> Result<Solution, SolveError> {
+        check_puzzle(self.width, self.height, &self.pieces)?;
+        let solution = Mutex::new(None);
+        self.run_tasks(|mut solver, cancelled| {
+            if !solver.current_grid().is_solved() {
+                while !cancelled.load(Ordering::Relaxed) && solver.step_propagate().is_ok() {}
+            }
+            match solver.current_solution() {
+                Some(found) => {
+                    solution.lock().unwrap().get_or_insert(found);
+                    false
+                }
+                None => true,
+            }
+        });
+        solution.into_inner().unwrap().ok_or(SolveError::Exhausted)
+    }
+
+    /// Counts every solution, each thread counts the solutions of its own tasks
+    pub fn count_solutions(&self) -> usize {
+        if check_puzzle(self.width, self.height, &self.pieces).is_err() {
+            return 0;
+        }
+        let count = AtomicUsize::new(0);
+        self.run_tasks(|mut solver, _| {
+            let solutions = if solver.current_grid().is_solved() { 1 } else { solver.count_solutions() };
+            count.fetch_add(solutions, Ordering::Relaxed);
+            true
+        });
+        count.into_inner()
+    }
+
+    /// Like [`SolverState::count_unique_solutions`], with the tallies of every thread merged
+    pub fn count_unique_solutions(&self, include_reflections: bool) -> SolutionCounts {
+        if check_puzzle(self.width, self.height, &self.pieces).is_err() {
+            return SolutionCounts { raw: 0, unique: 0 };
+        }
+        let new_tally = || SolutionTally::new(self.width, self.height, include_reflections, self.break_symmetry);
+        let tally = Mutex::new(new_tally());
+        self.run_tasks(|mut solver, _| {
+            let mut task_tally = new_tally();
+            if solver.current_grid().is_solved() {
+                task_tally.add(&solver.current_solution().unwrap());
+            }
+            for solution in solver.solutions() {
+                task_tally.add(&solution);
+            }
+            tally.lock().unwrap().merge(task_tally);
+            true
+        });
+        tally.into_inner().unwrap().counts()
+    }
+}
+
+/// A solver forked from `root` with the task's placements fixed, if they all still fit
+fn task_solver(root: &SolverState, task: &[usize]) -> Option<SolverState> {
+    let mut solver = root.fork();
+    task.iter().all(|&placement| solver.fix_placement(placement)).then_some(solver)
+}
@@ -1,20 +1,21 @@
-use crate::constraint_solver::PieceRotation;
+use crate::constraint_solver::{PieceOrientation, PieceRotation};
 use crate::piece::ConnectionType::{Double, NoConnection, Straight};
 use crate::piece::VisualCell::{CellEmpty, CellNCenter, CellNLeft, CellNRight, CellStraight, CellWeird1, CellWeird2};
-use std::collections::HashSet;
-use std::fmt::Debug;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::ops::{BitAnd, BitAndAssign, BitOrAssign};
 use std::mem::MaybeUninit;
 use std::ops::Deref;
 use std::sync::LazyLock;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub enum ConnectionType {
     NoConnection,
     Straight,
     Double,
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct Cell {
     pub right: ConnectionType,
     pub top: ConnectionType,
@@ -32,6 +33,112 @@ impl Cell {
     pub const fn rotate_90_ccw(&self) -> Cell {
         Cell { right: self.bottom, top: self.right, left: self.top, bottom: self.left }
     }
+
+    /// Mirrors the cell across its vertical axis
+    pub const fn mirror_horizontal(&self) -> Cell {
+        Cell { right: self.left, top: self.top, left: self.right, bottom: self.bottom }
+    }
+
+    /// A unique number below [`CELL_COUNT`] for every possible cell, its edges read as a base 3 number
+    pub const fn id(&self) -> usize {
+        self.right as usize + 3 * self.top as usize + 9 * self.left as usize + 27 * self.bottom as usize
+    }
+
+    pub const fn from_id(id: usize) -> Cell {
+        const fn connection(digit: usize) -> ConnectionType {
+            match digit % 3 {
+                0 => NoConnection,
+                1 => Straight,
+                _ => Double,
+            }
+        }
+        Cell { right: connection(id), top: connection(id / 3), left: connection(id / 9), bottom: connection(id / 27) }
+    }
+}
+
+/// How many different cells there are, every edge has one of 3 connection types
+pub const CELL_COUNT: usize = 81;
+
+/// A set of cells stored as one bit per possible cell, see [`Cell::id`]
+#[derive(Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub struct CellSet(u128);
+
+impl CellSet {
+    pub const EMPTY: CellSet = CellSet(0);
+
+    pub const fn insert(&mut self, cell: Cell) {
+        self.0 |= 1 << cell.id();
+    }
+
+    pub const fn contains(&self, cell: &Cell) -> bool {
+        self.0 & (1 << cell.id()) != 0
+    }
+
+    pub const fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn intersects(&self, other: CellSet) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Every cell in the set, in id order
+    pub fn iter(&self) -> impl Iterator<Item = Cell> + use<> {
+        let mut bits = self.0;
+        std::iter::from_fn(move || {
+            if bits == 0 {
+                return None;
+            }
+            let id = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            Some(Cell::from_id(id))
+        })
+    }
+
+    /// Every possible cell that satisfies `predicate`
+    pub fn all_matching(predicate: impl Fn(&Cell) -> bool) -> CellSet {
+        (0..CELL_COUNT).map(Cell::from_id).filter(predicate).collect()
+    }
+}
+
+impl FromIterator<Cell> for CellSet {
+    fn from_iter<T: IntoIterator<Item = Cell>>(iter: T) -> Self {
+        let mut set = CellSet::EMPTY;
+        for cell in iter {
+            set.insert(cell);
+        }
+        set
+    }
+}
+
+impl BitAnd for CellSet {
+    type Output = CellSet;
+
+    fn bitand(self, rhs: CellSet) -> CellSet {
+        CellSet(self.0 & rhs.0)
+    }
+}
+
+impl BitAndAssign for CellSet {
+    fn bitand_assign(&mut self, rhs: CellSet) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl BitOrAssign for CellSet {
+    fn bitor_assign(&mut self, rhs: CellSet) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl Debug for CellSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
 }
 
 // #[derive(Clone, Copy, Debug)]
@@ -39,42 +146,63 @@ impl Cell {
 //     image: &'static str,
 // }
 // pub type VisualCell = &'static str;
+/// The image drawn for a cell, its angle in degrees CCW and whether it is mirrored.
+/// A mirrored image is flipped horizontally before it is rotated.
 #[derive(Clone, Copy, Debug)]
 pub enum VisualCell {
     CellEmpty,
-    CellNCenter(u32),
-    CellNLeft(u32),
-    CellNRight(u32),
-    CellStraight(u32),
-    CellWeird1(u32),
-    CellWeird2(u32),
-    Other(&'static str, u32),
+    CellNCenter(u32, bool),
+    CellNLeft(u32, bool),
+    CellNRight(u32, bool),
+    CellStraight(u32, bool),
+    CellWeird1(u32, bool),
+    CellWeird2(u32, bool),
+    Other(&'static str, u32, bool),
 }
 
 impl VisualCell {
     pub fn get_image_path(&self) -> &'static str {
         match self {
             VisualCell::CellEmpty => "resources/cell_empty.png",
-            VisualCell::CellNCenter(_) => "resources/cell_n_center.png",
-            VisualCell::CellNLeft(_) => "resources/cell_n_left.png",
-            VisualCell::CellNRight(_) => "resources/cell_n_right.png",
-            VisualCell::CellStraight(_) => "resources/cell_straight.png",
-            VisualCell::CellWeird1(_) => "resources/cell_weird_1.png",
-            VisualCell::CellWeird2(_) => "resources/cell_weird_2.png",
-            VisualCell::Other(path, _) => path,
+            VisualCell::CellNCenter(..) => "resources/cell_n_center.png",
+            VisualCell::CellNLeft(..) => "resources/cell_n_left.png",
+            VisualCell::CellNRight(..) => "resources/cell_n_right.png",
+            VisualCell::CellStraight(..) => "resources/cell_straight.png",
+            VisualCell::CellWeird1(..) => "resources/cell_weird_1.png",
+            VisualCell::CellWeird2(..) => "resources/cell_weird_2.png",
+            VisualCell::Other(path, ..) => path,
         }
     }
 
     pub const fn rotate_90_ccw(mut self) -> Self {
         match &mut self {
             VisualCell::CellEmpty => (),
-            VisualCell::CellNCenter(angle) => *angle += 90,
-            VisualCell::CellNLeft(angle) => *angle += 90,
-            VisualCell::CellNRight(angle) => *angle += 90,
-            VisualCell::CellStraight(angle) => *angle += 90,
-            VisualCell::CellWeird1(angle) => *angle += 90,
-            VisualCell::CellWeird2(angle) => *angle += 90,
-            VisualCell::Other(_, angle) => *angle += 90,
+            VisualCell::CellNCenter(angle, _) => *angle += 90,
+            VisualCell::CellNLeft(angle, _) => *angle += 90,
+            VisualCell::CellNRight(angle, _) => *angle += 90,
+            VisualCell::CellStraight(angle, _) => *angle += 90,
+            VisualCell::CellWeird1(angle, _) => *angle += 90,
+            VisualCell::CellWeird2(angle, _) => *angle += 90,
+            VisualCell::Other(_, angle, _) => *angle += 90,
+        }
+        self
+    }
+
+    /// Mirrors the cell across its vertical axis.
+    /// Flipping an image rotated by some angle is the same as rotating the flipped image the other way.
+    pub const fn mirror_horizontal(mut self) -> Self {
+        match &mut self {
+            VisualCell::CellEmpty => (),
+            VisualCell::CellNCenter(angle, mirrored)
+            | VisualCell::CellNLeft(angle, mirrored)
+            | VisualCell::CellNRight(angle, mirrored)
+            | VisualCell::CellStraight(angle, mirrored)
+            | VisualCell::CellWeird1(angle, mirrored)
+            | VisualCell::CellWeird2(angle, mirrored)
+            | VisualCell::Other(_, angle, mirrored) => {
+                *angle = (360 - *angle % 360) % 360;
+                *mirrored = !*mirrored;
+            }
         }
         self
     }
@@ -82,13 +210,26 @@ impl VisualCell {
     pub const fn angle(&self) -> u32 {
         match self {
             VisualCell::CellEmpty => 0,
-            VisualCell::CellNCenter(angle) => *angle,
-            VisualCell::CellNLeft(angle) => *angle,
-            VisualCell::CellNRight(angle) => *angle,
-            VisualCell::CellStraight(angle) => *angle,
-            VisualCell::CellWeird1(angle) => *angle,
-            VisualCell::CellWeird2(angle) => *angle,
-            VisualCell::Other(_, angle) => *angle,
+            VisualCell::CellNCenter(angle, _) => *angle,
+            VisualCell::CellNLeft(angle, _) => *angle,
+            VisualCell::CellNRight(angle, _) => *angle,
+            VisualCell::CellStraight(angle, _) => *angle,
+            VisualCell::CellWeird1(angle, _) => *angle,
+            VisualCell::CellWeird2(angle, _) => *angle,
+            VisualCell::Other(_, angle, _) => *angle,
+        }
+    }
+
+    pub const fn is_mirrored(&self) -> bool {
+        match self {
+            VisualCell::CellEmpty => false,
+            VisualCell::CellNCenter(_, mirrored) => *mirrored,
+            VisualCell::CellNLeft(_, mirrored) => *mirrored,
+            VisualCell::CellNRight(_, mirrored) => *mirrored,
+            VisualCell::CellStraight(_, mirrored) => *mirrored,
+            VisualCell::CellWeird1(_, mirrored) => *mirrored,
+            VisualCell::CellWeird2(_, mirrored) => *mirrored,
+            VisualCell::Other(_, _, mirrored) => *mirrored,
         }
     }
 }
@@ -98,11 +239,18 @@ pub struct PieceDefinition<const WIDTH: usize, const HEIGHT: usize> {
     piece_id: usize,
     logical_cells: [[Cell; WIDTH]; HEIGHT],
     visual_cells: [[VisualCell; WIDTH]; HEIGHT],
+    flippable: bool,
 }
 
 impl<const WIDTH: usize, const HEIGHT: usize> PieceDefinition<WIDTH, HEIGHT> {
     pub const fn new(piece_id: usize, logical_cells: [[Cell; WIDTH]; HEIGHT], visual_cells: [[VisualCell; WIDTH]; HEIGHT]) -> Self {
-        Self { piece_id, logical_cells, visual_cells }
+        Self { piece_id, logical_cells, visual_cells, flippable: false }
+    }
+
+    /// Allows the piece to be flipped over, so it can also be placed mirrored
+    pub const fn flippable(mut self) -> Self {
+        self.flippable = true;
+        self
     }
 
     pub fn as_piece_ops(&self) -> &(dyn PieceOps) {
@@ -110,6 +258,32 @@ impl<const WIDTH: usize, const HEIGHT: usize> PieceDefinition<WIDTH, HEIGHT> {
     }
 }
 
+/// A piece whose size is only known at runtime, e.g. one loaded from a piece set file.
+/// Cells are stored row-major, the same layout `cells_flat` exposes.
+#[derive(Clone, Debug)]
+pub struct DynamicPieceDefinition {
+    piece_id: usize,
+    width: usize,
+    height: usize,
+    logical_cells: Vec<Cell>,
+    visual_cells: Vec<VisualCell>,
+    flippable: bool,
+}
+
+impl DynamicPieceDefinition {
+    pub fn new(piece_id: usize, width: usize, height: usize, logical_cells: Vec<Cell>, visual_cells: Vec<VisualCell>) -> Self {
+        assert_eq!(logical_cells.len(), width * height, "Logical cell count does not match piece size");
+        assert_eq!(visual_cells.len(), width * height, "Visual cell count does not match piece size");
+        Self { piece_id, width, height, logical_cells, visual_cells, flippable: false }
+    }
+
+    /// Allows the piece to be flipped over, so it can also be placed mirrored
+    pub fn flippable(mut self) -> Self {
+        self.flippable = true;
+        self
+    }
+}
+
 pub static CELL_EMPTY: Cell = Cell { right: NoConnection, top: NoConnection, left: NoConnection, bottom: NoConnection };
 
 pub static P1: PieceDefinition<4, 1> = PieceDefinition::new(
@@ -120,7 +294,7 @@ pub static P1: PieceDefinition<4, 1> = PieceDefinition::new(
         Cell { right: NoConnection, top: NoConnection, left: NoConnection, bottom: Double },
         Cell { right: NoConnection, top: NoConnection, left: NoConnection, bottom: Double },
     ]],
-    [[CellEmpty, CellEmpty, CellNRight(0), CellNLeft(0)]],
+    [[CellEmpty, CellEmpty, CellNRight(0, false), CellNLeft(0, false)]],
 );
 pub static P2: PieceDefinition<1, 3> = PieceDefinition::new(
     2, //
@@ -129,7 +303,7 @@ pub static P2: PieceDefinition<1, 3> = PieceDefinition::new(
         [Cell { right: NoConnection, top: NoConnection, left: Double, bottom: NoConnection}],                                                                     //
         [Cell { right: Double, top: NoConnection, left: NoConnection, bottom: Straight }],                                                                      //
     ], //
-    [[CellNCenter(180)], [CellNLeft(270)], [CellNLeft(90)]],
+    [[CellNCenter(180, false)], [CellNLeft(270, false)], [CellNLeft(90, false)]],
 );
 pub static P3: PieceDefinition<1, 3> = PieceDefinition::new(
     3,                                                //
@@ -138,7 +312,7 @@ pub static P3: PieceDefinition<1, 3> = PieceDefinition::new(
         [CELL_EMPTY], //
         [CELL_EMPTY], //
     ], //
-    [[CellNRight(180)], [CellEmpty], [CellEmpty]],
+    [[CellNRight(180, false)], [CellEmpty], [CellEmpty]],
 );
 pub static P4: PieceDefinition<1, 3> = PieceDefinition::new(
     4,                                                    //
@@ -147,22 +321,22 @@ pub static P4: PieceDefinition<1, 3> = PieceDefinition::new(
         [CELL_EMPTY], //
         [Cell { right: NoConnection, top: NoConnection, left: NoConnection, bottom: Double}], //
     ], //
-    [[CellNLeft(90)], [CellStraight(0)], [CellNCenter(0)]],
+    [[CellNLeft(90, false)], [CellStraight(0, false)], [CellNCenter(0, false)]],
 );
 pub static P5: PieceDefinition<3, 1> = PieceDefinition::new(
     5,                                              //
     [[Cell {right: NoConnection, top: NoConnection, left: NoConnection, bottom: Double}, Cell {right: NoConnection, top: Double, left: NoConnection, bottom: NoConnection}, CELL_EMPTY]], //
-    [[CellNRight(0), CellNRight(180), CellEmpty]],
+    [[CellNRight(0, false), CellNRight(180, false), CellEmpty]],
 );
 pub static P6: PieceDefinition<3, 1> = PieceDefinition::new(
     6,                                            //
     [[Cell {right: NoConnection, top: Double, left: NoConnection, bottom: NoConnection}, Cell {right: NoConnection, top: NoConnection, left: NoConnection, bottom: Double}, CELL_EMPTY]], //
-    [[CellNLeft(180), CellNLeft(0), CellEmpty]],
+    [[CellNLeft(180, false), CellNLeft(0, false), CellEmpty]],
 );
 pub static P7: PieceDefinition<2, 1> = PieceDefinition::new(
     7,                              //
     [[Cell{right: NoConnection, top: Straight, left: Double, bottom: NoConnection}, Cell{ right: NoConnection, top: Straight, left: NoConnection, bottom: Double }]], //
-    [[CellNLeft(270), CellNCenter(0)]],
+    [[CellNLeft(270, false), CellNCenter(0, false)]],
 );
 pub static P8: PieceDefinition<1, 3> = PieceDefinition::new(
     8,                                                          //
@@ -170,7 +344,7 @@ pub static P8: PieceDefinition<1, 3> = PieceDefinition::new(
         [Cell{right: NoConnection, top: Double, left: NoConnection, bottom: NoConnection}], //
         [CELL_EMPTY], //
         [Cell {right: NoConnection, top: NoConnection, left: Straight, bottom: NoConnection}]], //
-    [[CellNCenter(180)], [CellNCenter(0)], [CellNRight(180)]],
+    [[CellNCenter(180, false)], [CellNCenter(0, false)], [CellNRight(180, false)]],
 );
 pub static P9: PieceDefinition<2, 1> = PieceDefinition::new(
     9,                          //
@@ -183,7 +357,7 @@ pub static P10_P11: PieceDefinition<2, 2> = PieceDefinition::new(
         [Cell{right: NoConnection, top: Double, left: NoConnection, bottom: NoConnection}, Cell{right: Straight, top: NoConnection, left: NoConnection, bottom: NoConnection}],//
         [CELL_EMPTY, CELL_EMPTY],//
     ],   //
-    [[CellWeird1(0), CellNRight(0)], [CellEmpty, CellWeird2(0)]], //
+    [[CellWeird1(0, false), CellNRight(0, false)], [CellEmpty, CellWeird2(0, false)]], //
 );
 pub static P12: PieceDefinition<1, 2> = PieceDefinition::new(
     12,                                 //
@@ -191,20 +365,20 @@ pub static P12: PieceDefinition<1, 2> = PieceDefinition::new(
         [Cell {right: NoConnection, top: NoConnection, left: Straight, bottom: NoConnection}], //
         [Cell {right: Straight, top: NoConnection, left: NoConnection, bottom: NoConnection}],//
     ], //
-    [[CellNLeft(0)], [CellNLeft(180)]], //
+    [[CellNLeft(0, false)], [CellNLeft(180, false)]], //
 );
 pub static P13: PieceDefinition<2, 1> = PieceDefinition::new(
     13,                                     //
     [[Cell{right: NoConnection, top: Straight, left: NoConnection, bottom: NoConnection}, Cell{right: Straight, top: NoConnection, left: NoConnection, bottom: NoConnection}]], //
-    [[CellNRight(90), CellNCenter(270)]],   //
+    [[CellNRight(90, false), CellNCenter(270, false)]],   //
 );
 pub static P14: PieceDefinition<2, 1> = PieceDefinition::new(
     14,                                   //
     [[Cell{right: NoConnection, top: Straight, left: NoConnection, bottom: NoConnection}, Cell{right: NoConnection, top: Straight, left: NoConnection, bottom: NoConnection}]], //
-    [[CellNRight(90), CellNLeft(270)]],   //
+    [[CellNRight(90, false), CellNLeft(270, false)]],   //
 );
 
-pub trait PieceOps: Debug + Sync {
+pub trait PieceOps: Debug + Send + Sync {
     fn piece_id(&self) -> usize;
     fn width(&self) -> usize;
     fn height(&self) -> usize;
@@ -219,6 +393,16 @@ pub trait PieceOps: Debug + Sync {
     fn rotate_90(&self) -> Box<dyn PieceOps>;
 
     fn rotate(&self, rotation: PieceRotation) -> Box<dyn PieceOps>;
+
+    /// Whether the piece may be flipped over and placed mirrored
+    fn can_flip(&self) -> bool;
+
+    /// Mirrors the piece across its vertical axis
+    fn mirror(&self) -> Box<dyn PieceOps>;
+
+    fn orient(&self, orientation: PieceOrientation) -> Box<dyn PieceOps> {
+        if orientation.is_mirrored() { self.mirror().rotate(orientation.rotation()) } else { self.rotate(orientation.rotation()) }
+    }
 }
 
 impl PartialEq for &dyn PieceOps {
@@ -281,7 +465,7 @@ impl<const WIDTH: usize, const HEIGHT: usize> PieceOps for PieceDefinition<WIDTH
             // the layout of MaybeUninit<T> is the same as T
             rotated_visual_cells.as_ptr().cast::<[[VisualCell; HEIGHT]; WIDTH]>().read()
         };
-        let new = PieceDefinition::<HEIGHT, WIDTH> { piece_id: self.piece_id, logical_cells: rotated_logical_cells, visual_cells: rotated_visual_cells };
+        let new = PieceDefinition::<HEIGHT, WIDTH> { piece_id: self.piece_id, logical_cells: rotated_logical_cells, visual_cells: rotated_visual_cells, flippable: self.flippable };
         Box::new(new)
     }
 
@@ -293,6 +477,134 @@ impl<const WIDTH: usize, const HEIGHT: usize> PieceOps for PieceDefinition<WIDTH
             PieceRotation::CCW270 => self.rotate_90().rotate_90().rotate_90(),
         }
     }
+
+    fn can_flip(&self) -> bool {
+        self.flippable
+    }
+
+    fn mirror(&self) -> Box<dyn PieceOps> {
+        let mut mirrored = *self;
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                mirrored.logical_cells[y][x] = self.logical_cells[y][WIDTH - x - 1].mirror_horizontal();
+                mirrored.visual_cells[y][x] = self.visual_cells[y][WIDTH - x - 1].mirror_horizontal();
+            }
+        }
+        Box::new(mirrored)
+    }
+}
+
+impl PieceOps for DynamicPieceDefinition {
+    fn piece_id(&self) -> usize {
+        self.piece_id
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn cells(&self) -> Vec<Vec<Cell>> {
+        self.logical_cells.chunks(self.width).map(|cells| cells.to_vec()).collect()
+    }
+
+    fn cells_flat(&self) -> &[Cell] {
+        &self.logical_cells
+    }
+
+    fn visual_cells(&self) -> Vec<Vec<VisualCell>> {
+        self.visual_cells.chunks(self.width).map(|cells| cells.to_vec()).collect()
+    }
+
+    fn visual_cells_flat(&self) -> &[VisualCell] {
+        &self.visual_cells
+    }
+
+    fn rotate_90(&self) -> Box<dyn PieceOps> {
+        // The rotated piece is `height` cells wide and `width` cells tall
+        let mut rotated_logical_cells = Vec::with_capacity(self.logical_cells.len());
+        let mut rotated_visual_cells = Vec::with_capacity(self.visual_cells.len());
+        for x in 0..self.width {
+            for y in 0..self.height {
+                let index = y * self.width + (self.width - x - 1);
+                rotated_logical_cells.push(self.logical_cells[index].rotate_90_ccw());
+                rotated_visual_cells.push(self.visual_cells[index].rotate_90_ccw());
+            }
+        }
+        Box::new(DynamicPieceDefinition {
+            piece_id: self.piece_id,
+            width: self.height,
+            height: self.width,
+            logical_cells: rotated_logical_cells,
+            visual_cells: rotated_visual_cells,
+            flippable: self.flippable,
+        })
+    }
+
+    fn rotate(&self, rotation: PieceRotation) -> Box<dyn PieceOps> {
+        match rotation {
+            PieceRotation::CCW0 => Box::new(self.clone()),
+            PieceRotation::CCW90 => self.rotate_90(),
+            PieceRotation::CCW180 => self.rotate_90().rotate_90(),
+            PieceRotation::CCW270 => self.rotate_90().rotate_90().rotate_90(),
+        }
+    }
+
+    fn can_flip(&self) -> bool {
+        self.flippable
+    }
+
+    fn mirror(&self) -> Box<dyn PieceOps> {
+        let mut mirrored = self.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = y * self.width + (self.width - x - 1);
+                mirrored.logical_cells[y * self.width + x] = self.logical_cells[index].mirror_horizontal();
+                mirrored.visual_cells[y * self.width + x] = self.visual_cells[index].mirror_horizontal();
+            }
+        }
+        Box::new(mirrored)
+    }
+}
+
+/// A piece laid down in one of its orientations
+#[derive(Debug)]
+pub struct OrientedPiece {
+    pub orientation: PieceOrientation,
+    pub piece: Box<dyn PieceOps>,
+}
+
+/// Every distinct orientation of each piece in a piece domain, computed once up front.
+/// Orientations that produce the same cells as an earlier one (e.g. a symmetric piece rotated 180 degrees) are left out.
+#[derive(Debug)]
+pub struct PieceOrientations {
+    by_piece_id: HashMap<usize, Vec<OrientedPiece>>,
+}
+
+impl PieceOrientations {
+    pub fn new(pieces: &[&dyn PieceOps]) -> PieceOrientations {
+        let by_piece_id = pieces.iter().map(|piece| (piece.piece_id(), unique_orientations(*piece))).collect();
+        PieceOrientations { by_piece_id }
+    }
+
+    pub fn of(&self, piece_id: usize) -> &[OrientedPiece] {
+        &self.by_piece_id[&piece_id]
+    }
+}
+
+fn unique_orientations(piece: &dyn PieceOps) -> Vec<OrientedPiece> {
+    let mut seen_cells = HashSet::new();
+    let mut orientations = vec![];
+    for &orientation in PieceOrientation::allowed_for(piece) {
+        let oriented = piece.orient(orientation);
+        if seen_cells.insert((oriented.width(), oriented.cells_flat().to_vec())) {
+            orientations.push(OrientedPiece { orientation, piece: oriented });
+        }
+    }
+    orientations
 }
 
 pub fn get_piece_domain() -> &'static Vec<&'static (dyn PieceOps)> {
@@ -317,20 +629,26 @@ pub fn get_piece_domain() -> &'static Vec<&'static (dyn PieceOps)> {
 }
 
 pub fn get_full_cell_domain() -> &'static Vec<Cell> {
-    static FULL_CELL_DOMAIN: LazyLock<Vec<Cell>> = LazyLock::new(|| {
-        let piece_domain = get_piece_domain();
-        let mut full_cell_domain = HashSet::new();
-
-        for piece in piece_domain {
-            for cell in piece.cells().into_iter().flatten() {
-                full_cell_domain.insert(cell);
-                full_cell_domain.insert(cell.rotate_90_ccw());
-                full_cell_domain.insert(cell.rotate_90_ccw().rotate_90_ccw());
-                full_cell_domain.insert(cell.rotate_90_ccw().rotate_90_ccw().rotate_90_ccw());
-            }
+    static FULL_CELL_DOMAIN: LazyLock<Vec<Cell>> = LazyLock::new(|| cell_domain_for_pieces(get_piece_domain()));
+    &FULL_CELL_DOMAIN
+}
+
+/// Every cell (in every rotation, and mirrored for flippable pieces) that can appear on a board filled from `pieces`
+pub fn cell_domain_for_pieces(pieces: &[&dyn PieceOps]) -> Vec<Cell> {
+    let mut full_cell_domain = HashSet::new();
+
+    for piece in pieces {
+        let mut cells = piece.cells().into_iter().flatten().collect::<Vec<_>>();
+        if piece.can_flip() {
+            cells.extend(piece.mirror().cells_flat());
         }
+        for cell in cells {
+            full_cell_domain.insert(cell);
+            full_cell_domain.insert(cell.rotate_90_ccw());
+            full_cell_domain.insert(cell.rotate_90_ccw().rotate_90_ccw());
+            full_cell_domain.insert(cell.rotate_90_ccw().rotate_90_ccw().rotate_90_ccw());
+        }
+    }
 
-        full_cell_domain.into_iter().collect()
-    });
-    &FULL_CELL_DOMAIN
+    full_cell_domain.into_iter().collect()
 }
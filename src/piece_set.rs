@@ -0,0 +1,187 @@
+//! Loading piece sets from text files, so a new physical puzzle doesn't need to be compiled in.
+//!
+//! The format is line based, `#` starts a comment and blank lines are ignored:
+//!
+//! ```text
+//! # piece <id> <width>x<height> [flippable], followed by <height> rows of <width> cells
+//! piece 7 2x1
+//! nsdn:n_left@270 nsnd:n_center@0
+//! ```
+//!
+//! A cell is written as its four edges in `right top left bottom` order, where each edge is
+//! `n` (no connection), `s` (straight) or `d` (double), then `:` and its visual cell. The visual
+//! cell is one of `empty`, `n_center`, `n_left`, `n_right`, `straight`, `weird_1`, `weird_2` or
+//! a path to any other image, optionally followed by `@<angle>`. A leading `~` marks an image as
+//! mirrored. Pieces marked `flippable` may also be placed flipped over.
+
+use crate::piece::ConnectionType::{Double, NoConnection, Straight};
+use crate::piece::{Cell, ConnectionType, DynamicPieceDefinition, PieceOps, VisualCell};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct PieceSetError {
+    /// 1-based line the error was found on, 0 if it isn't tied to a line
+    pub line: usize,
+    pub message: String,
+}
+
+impl PieceSetError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        PieceSetError { line, message: message.into() }
+    }
+}
+
+impl Display for PieceSetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.line == 0 { write!(f, "{}", self.message) } else { write!(f, "line {}: {}", self.line, self.message) }
+    }
+}
+
+impl std::error::Error for PieceSetError {}
+
+/// Reads and parses the piece set at `path`, see [`parse_piece_set`]
+pub fn load_piece_set<P: AsRef<Path>>(path: P) -> Result<Vec<&'static dyn PieceOps>, PieceSetError> {
+    let source = std::fs::read_to_string(path.as_ref()).map_err(|err| PieceSetError::new(0, format!("Failed to read {}: {}", path.as_ref().display(), err)))?;
+    parse_piece_set(&source)
+}
+
+/// Parses a piece set into a piece domain.
+///
+/// Pieces are leaked so they can be shared as `&'static dyn PieceOps` just like the compiled-in pieces,
+/// a piece set is expected to be loaded once and live for the rest of the program.
+pub fn parse_piece_set(source: &str) -> Result<Vec<&'static dyn PieceOps>, PieceSetError> {
+    let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line.split('#').next().unwrap().trim())).filter(|(_, line)| !line.is_empty());
+
+    let mut pieces: Vec<&'static dyn PieceOps> = vec![];
+    let mut seen_ids = HashSet::new();
+    while let Some((line_number, line)) = lines.next() {
+        let (piece_id, width, height, flippable) = parse_piece_header(line_number, line)?;
+        if !seen_ids.insert(piece_id) {
+            return Err(PieceSetError::new(line_number, format!("Duplicate piece id {}", piece_id)));
+        }
+
+        let mut logical_cells = Vec::with_capacity(width * height);
+        let mut visual_cells = Vec::with_capacity(width * height);
+        for row in 0..height {
+            let Some((line_number, line)) = lines.next() else {
+                return Err(PieceSetError::new(0, format!("Piece {} ended after {} of {} rows", piece_id, row, height)));
+            };
+            let row_cells = line.split_whitespace().map(|cell| parse_cell(line_number, cell)).collect::<Result<Vec<_>, _>>()?;
+            if row_cells.len() != width {
+                return Err(PieceSetError::new(line_number, format!("Piece {} row has {} cells, expected {}", piece_id, row_cells.len(), width)));
+            }
+            for (logical_cell, visual_cell) in row_cells {
+                logical_cells.push(logical_cell);
+                visual_cells.push(visual_cell);
+            }
+        }
+
+        let mut piece = DynamicPieceDefinition::new(piece_id, width, height, logical_cells, visual_cells);
+        if flippable {
+            piece = piece.flippable();
+        }
+        pieces.push(Box::leak(Box::new(piece)));
+    }
+
+    if pieces.is_empty() {
+        return Err(PieceSetError::new(0, "Piece set doesn't contain any pieces"));
+    }
+    Ok(pieces)
+}
+
+fn parse_piece_header(line_number: usize, line: &str) -> Result<(usize, usize, usize, bool), PieceSetError> {
+    let invalid_header = || PieceSetError::new(line_number, format!("Expected `piece <id> <width>x<height> [flippable]`, found `{}`", line));
+
+    let mut parts = line.split_whitespace();
+    if parts.next() != Some("piece") {
+        return Err(invalid_header());
+    }
+    let piece_id = parts.next().and_then(|id| id.parse().ok()).ok_or_else(invalid_header)?;
+    let (width, height) = parts.next().and_then(|size| size.split_once('x')).ok_or_else(invalid_header)?;
+    let width: usize = width.parse().map_err(|_| invalid_header())?;
+    let height: usize = height.parse().map_err(|_| invalid_header())?;
+    let flippable = match parts.next() {
+        None => false,
+        Some("flippable") => true,
+        Some(_) => return Err(invalid_header()),
+    };
+    if parts.next().is_some() {
+        return Err(invalid_header());
+    }
+    if width == 0 || height == 0 {
+        return Err(PieceSetError::new(line_number, format!("Piece {} has an empty size", piece_id)));
+    }
+
+    Ok((piece_id, width, height, flippable))
+}
+
+fn parse_cell(line_number: usize, cell: &str) -> Result<(Cell, VisualCell), PieceSetError> {
+    let (edges, visual) = cell.split_once(':').ok_or_else(|| PieceSetError::new(line_number, format!("Cell `{}` is missing its visual cell", cell)))?;
+
+    let edges = edges.chars().map(|edge| parse_connection(line_number, edge)).collect::<Result<Vec<_>, _>>()?;
+    let [right, top, left, bottom] = edges[..] else {
+        return Err(PieceSetError::new(line_number, format!("Cell `{}` must have exactly 4 edges", cell)));
+    };
+
+    Ok((Cell { right, top, left, bottom }, parse_visual_cell(line_number, visual)?))
+}
+
+fn parse_connection(line_number: usize, edge: char) -> Result<ConnectionType, PieceSetError> {
+    match edge {
+        'n' => Ok(NoConnection),
+        's' => Ok(Straight),
+        'd' => Ok(Double),
+        _ => Err(PieceSetError::new(line_number, format!("Unknown edge type `{}`, expected one of `n`, `s` or `d`", edge))),
+    }
+}
+
+fn parse_visual_cell(line_number: usize, visual: &str) -> Result<VisualCell, PieceSetError> {
+    let (visual, mirrored) = match visual.strip_prefix('~') {
+        Some(visual) => (visual, true),
+        None => (visual, false),
+    };
+    let (name, angle) = match visual.split_once('@') {
+        Some((name, angle)) => (name, angle.parse().map_err(|_| PieceSetError::new(line_number, format!("Invalid angle `{}`", angle)))?),
+        None => (visual, 0),
+    };
+
+    Ok(match name {
+        "empty" => VisualCell::CellEmpty,
+        "n_center" => VisualCell::CellNCenter(angle, mirrored),
+        "n_left" => VisualCell::CellNLeft(angle, mirrored),
+        "n_right" => VisualCell::CellNRight(angle, mirrored),
+        "straight" => VisualCell::CellStraight(angle, mirrored),
+        "weird_1" => VisualCell::CellWeird1(angle, mirrored),
+        "weird_2" => VisualCell::CellWeird2(angle, mirrored),
+        "" => return Err(PieceSetError::new(line_number, "Empty visual cell")),
+        // Like the pieces themselves, image paths live as long as the piece set
+        path => VisualCell::Other(Box::leak(path.to_owned().into_boxed_str()), angle, mirrored),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pieces() {
+        let pieces = parse_piece_set("# comment\npiece 7 2x1 flippable\nnsdn:n_left@270 nsnd:n_center@0\n").unwrap();
+        assert_eq!(pieces.len(), 1);
+        assert_eq!((pieces[0].piece_id(), pieces[0].width(), pieces[0].height()), (7, 2, 1));
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_cell() {
+        let err = parse_piece_set("piece 7 2x1\n\nnsdn:n_left nsxn:n_center\n").unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn rejects_a_short_piece() {
+        let err = parse_piece_set("piece 7 1x2\nnsdn:empty\n").unwrap_err();
+        assert_eq!(err.line, 0);
+        assert!(err.message.contains("ended after 1 of 2 rows"), "{}", err.message);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::constraint_solver::PieceOrientation;
+use crate::piece::{Cell, PieceOps, PieceOrientations};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// One way to put a piece on the board: an orientation with its top left corner at (`x`, `y`)
+#[derive(Debug)]
+pub struct Placement {
+    pub piece_id: usize,
+    pub orientation: PieceOrientation,
+    pub x: usize,
+    pub y: usize,
+    /// Index of the piece in the piece domain the table was built from
+    pub(crate) piece_index: usize,
+    /// Index of the orientation in [`PieceOrientations::of`]
+    orientation_index: usize,
+    /// Board position and cell of everything the placement covers
+    pub(crate) cells: Vec<(usize, usize, Cell)>,
+}
+
+/// Every placement of every piece that fits inside a board, computed once for a board and piece set
+#[derive(Debug)]
+pub struct PlacementTable {
+    pub orientations: PieceOrientations,
+    pub placements: Vec<Placement>,
+    pub(crate) piece_count: usize,
+    width: usize,
+    /// Indices of the placements covering each cell, row-major
+    by_cell: Vec<Vec<usize>>,
+    /// Placements are generated piece by piece, so each piece's are one contiguous range
+    by_piece: Vec<Range<usize>>,
+    piece_indices: HashMap<usize, usize>,
+}
+
+impl PlacementTable {
+    pub fn new(width: usize, height: usize, pieces: &[&dyn PieceOps]) -> PlacementTable {
+        let orientations = PieceOrientations::new(pieces);
+        let mut placements = vec![];
+        let mut by_cell = vec![vec![]; width * height];
+        let mut by_piece = vec![];
+        for (piece_index, piece) in pieces.iter().enumerate() {
+            let first_placement = placements.len();
+            for (orientation_index, oriented) in orientations.of(piece.piece_id()).iter().enumerate() {
+                let (piece_width, piece_height) = (oriented.piece.width(), oriented.piece.height());
+                if piece_width > width || piece_height > height {
+                    continue;
+                }
+                for y in 0..=height - piece_height {
+                    for x in 0..=width - piece_width {
+                        let cells = oriented.piece.cells_flat().iter().enumerate().map(|(i, cell)| (x + i % piece_width, y + i / piece_width, *cell)).collect::<Vec<_>>();
+                        for (cell_x, cell_y, _) in &cells {
+                            by_cell[cell_y * width + cell_x].push(placements.len());
+                        }
+                        placements.push(Placement { piece_id: piece.piece_id(), orientation: oriented.orientation, x, y, piece_index, orientation_index, cells });
+                    }
+                }
+            }
+            by_piece.push(first_placement..placements.len());
+        }
+        let piece_indices = pieces.iter().enumerate().map(|(index, piece)| (piece.piece_id(), index)).collect();
+
+        PlacementTable { orientations, placements, piece_count: pieces.len(), width, by_cell, by_piece, piece_indices }
+    }
+
+    /// Indices of every placement covering the cell at (`x`, `y`)
+    pub fn covering(&self, x: usize, y: usize) -> &[usize] {
+        &self.by_cell[y * self.width + x]
+    }
+
+    /// Indices of every placement of the piece at `piece_index`
+    pub fn of_piece(&self, piece_index: usize) -> Range<usize> {
+        self.by_piece[piece_index].clone()
+    }
+
+    pub fn piece_index(&self, piece_id: usize) -> usize {
+        self.piece_indices[&piece_id]
+    }
+
+    /// The piece turned the way the placement needs it
+    pub fn oriented_piece(&self, placement: &Placement) -> &dyn PieceOps {
+        &*self.orientations.of(placement.piece_id)[placement.orientation_index].piece
+    }
+}
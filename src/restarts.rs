@@ -0,0 +1,123 @@
+//! Restarting the search with reseeded heuristics whenever a run backtracks too often, so an unlucky
+//! ordering that leads into a huge dead subtree is abandoned instead of searched to the end.
+
+use crate::constraint_solver::{SolveError, Solution, SolverState};
+use std::time::{Duration, Instant};
+
+/// How many backtracks each run may make before the search restarts.
+/// Built with [`RestartSchedule::luby`] or [`RestartSchedule::geometric`], which make sure the cutoffs keep growing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RestartSchedule(Schedule);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Schedule {
+    Luby { unit: usize },
+    Geometric { initial: usize, factor: f64 },
+}
+
+impl RestartSchedule {
+    /// `unit` times the Luby sequence 1, 1, 2, 1, 1, 2, 4, 1, ..., `unit` has to be at least 1
+    pub fn luby(unit: usize) -> RestartSchedule {
+        assert!(unit > 0, "The Luby unit must be at least 1");
+        RestartSchedule(Schedule::Luby { unit })
+    }
+
+    /// `initial`, then `factor` times the previous cutoff. `initial` has to be at least 1 and `factor` above 1,
+    /// cutoffs that stop growing make the search restart forever on a puzzle without a solution.
+    pub fn geometric(initial: usize, factor: f64) -> RestartSchedule {
+        assert!(initial > 0 && factor > 1.0, "Geometric cutoffs must start at 1 or more and grow by a factor above 1, got {} and {}", initial, factor);
+        RestartSchedule(Schedule::Geometric { initial, factor })
+    }
+
+    /// The cutoff of the `restart`th run, counting from 0. Every run may backtrack at least once.
+    pub fn cutoff(&self, restart: usize) -> usize {
+        let cutoff = match self.0 {
+            Schedule::Luby { unit } => unit.saturating_mul(luby(restart + 1)),
+            Schedule::Geometric { initial, factor } => (initial as f64 * factor.powi(restart as i32)).min(usize::MAX as f64) as usize,
+        };
+        cutoff.max(1)
+    }
+}
+
+/// The `i`th element (counting from 1) of the Luby sequence
+fn luby(mut i: usize) -> usize {
+    loop {
+        let mut k = 1;
+        while (1 << k) - 1 < i {
+            k += 1;
+        }
+        if i == (1 << k) - 1 {
+            return 1 << (k - 1);
+        }
+        i -= (1 << (k - 1)) - 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunOutcome {
+    Solved,
+    /// The run searched everything without hitting its cutoff, so there is no solution
+    Exhausted,
+    CutOff,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestartStats {
+    /// The seed of the run, passing it in a [`SolverConfig`](crate::constraint_solver::SolverConfig) repeats the run.
+    /// Every run after the first breaks ties at random, see [`SolverState::solve_with_restarts`].
+    pub seed: u64,
+    pub cutoff: usize,
+    pub backtracks: usize,
+    pub duration: Duration,
+    pub outcome: RunOutcome,
+}
+
+#[derive(Debug)]
+pub struct RestartReport {
+    pub result: Result<Solution, SolveError>,
+    /// Every run in the order they were made, the last one found the solution or exhausted the search
+    pub restarts: Vec<RestartStats>,
+}
+
+impl SolverState {
+    /// Searches for a solution, restarting with the next seed whenever a run makes more backtracks than `schedule` allows.
+    /// The `n`th run is seeded with the solver's seed plus `n`. The heuristics stay the same, but if they aren't randomised
+    /// (see [`SolverConfig::randomized`](crate::constraint_solver::SolverConfig::randomized)) the runs after the first
+    /// break their ties at random, otherwise every run would repeat the same search.
+    /// Cutoffs keep growing, so the search still ends up exhausting the search space if there is no solution.
+    pub fn solve_with_restarts(&mut self, schedule: RestartSchedule) -> RestartReport {
+        if let Err(err) = self.validate_input() {
+            return RestartReport { result: Err(err), restarts: vec![] };
+        }
+
+        let first_seed = self.seed();
+        let mut restarts = vec![];
+        loop {
+            let restart = restarts.len();
+            if restart > 0 {
+                if restart == 1 && !self.config().randomize {
+                    self.randomize_ties();
+                }
+                self.restart(first_seed.wrapping_add(restart as u64));
+            }
+            let cutoff = schedule.cutoff(restart);
+            let start = Instant::now();
+            let backtracks_before = self.stats().backtracks;
+            let outcome = loop {
+                if self.stats().backtracks - backtracks_before >= cutoff {
+                    break RunOutcome::CutOff;
+                }
+                if self.step_propagate().is_err() {
+                    break if self.current_grid().is_solved() { RunOutcome::Solved } else { RunOutcome::Exhausted };
+                }
+            };
+            restarts.push(RestartStats { seed: self.seed(), cutoff, backtracks: self.stats().backtracks - backtracks_before, duration: start.elapsed(), outcome });
+
+            match outcome {
+                RunOutcome::Solved => return RestartReport { result: Ok(self.current_solution().unwrap()), restarts },
+                RunOutcome::Exhausted => return RestartReport { result: Err(SolveError::Exhausted), restarts },
+                RunOutcome::CutOff => {}
+            }
+        }
+    }
+}
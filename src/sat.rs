@@ -0,0 +1,192 @@
+//! Encoding a puzzle as CNF in the DIMACS format, so it can be cross-checked with any off-the-shelf SAT solver,
+//! and decoding the solver's model back into placements.
+//!
+//! Variables `1..=placement_count` stand for placing a piece in some orientation at some offset, one for every
+//! placement the empty grid hasn't already ruled out (so the border is taken care of). Every cell and every piece
+//! is covered by exactly one placement. Edges between cells get one variable per connection type, at most one
+//! of them can be true and a placement implies the connection it puts on each edge along its outline.
+
+use crate::constraint_solver::{Grid, Solution, SolverMove};
+use crate::piece::{ConnectionType, PieceOps};
+use std::fmt::{Display, Formatter, Write};
+
+const CONNECTION_TYPES: [ConnectionType; 3] = [ConnectionType::NoConnection, ConnectionType::Straight, ConnectionType::Double];
+
+#[derive(Debug)]
+pub struct CnfEncoding {
+    pub variable_count: usize,
+    /// Each clause is a list of literals, a negative literal is the negated variable
+    pub clauses: Vec<Vec<i64>>,
+    /// The placement (in the grid's placement table) each placement variable stands for, variable `i` is at `i - 1`
+    placement_variables: Vec<usize>,
+    /// The empty board, decoded models are placed on a copy of it
+    grid: Grid,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ModelError {
+    /// The SAT solver proved the puzzle has no solution
+    Unsatisfiable,
+    /// The model couldn't be read or doesn't describe a solution
+    Invalid(String),
+}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelError::Unsatisfiable => write!(f, "The SAT solver found the puzzle unsatisfiable"),
+            ModelError::Invalid(message) => write!(f, "Invalid model: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+/// Encodes the `width` x `height` board with `pieces`
+pub fn encode_puzzle(width: usize, height: usize, pieces: &[&'static dyn PieceOps]) -> CnfEncoding {
+    let grid = Grid::with_pieces(width, height, pieces);
+    let placement_table = grid.placement_table();
+    let placement_variables = (0..placement_table.placements.len()).filter(|index| grid.is_placement_live(*index)).collect::<Vec<_>>();
+
+    let mut variable_count = placement_variables.len();
+    let mut clauses = vec![];
+    let mut by_piece = vec![vec![]; placement_table.piece_count];
+    let mut by_cell = vec![vec![]; width * height];
+    // Every edge between two cells, with a variable for each connection type it could have
+    let horizontal_edges = (0..height * width.saturating_sub(1)).map(|_| new_connection_variables(&mut variable_count)).collect::<Vec<_>>();
+    let vertical_edges = (0..height.saturating_sub(1) * width).map(|_| new_connection_variables(&mut variable_count)).collect::<Vec<_>>();
+    for edge in horizontal_edges.iter().chain(&vertical_edges) {
+        at_most_one(&mut clauses, edge);
+    }
+
+    for (variable, &index) in (1..).zip(&placement_variables) {
+        let placement = &placement_table.placements[index];
+        by_piece[placement.piece_index].push(variable);
+        let piece = placement_table.oriented_piece(placement);
+        let inside = |x: usize, y: usize| (placement.x..placement.x + piece.width()).contains(&x) && (placement.y..placement.y + piece.height()).contains(&y);
+        let mut implies = |connection_variables: &[i64; 3], connection: ConnectionType| clauses.push(vec![-variable, connection_variables[connection as usize]]);
+
+        for &(x, y, cell) in &placement.cells {
+            by_cell[y * width + x].push(variable);
+            if x + 1 < width && !inside(x + 1, y) {
+                implies(&horizontal_edges[y * (width - 1) + x], cell.right);
+            }
+            if x > 0 && !inside(x - 1, y) {
+                implies(&horizontal_edges[y * (width - 1) + x - 1], cell.left);
+            }
+            if y + 1 < height && !inside(x, y + 1) {
+                implies(&vertical_edges[y * width + x], cell.bottom);
+            }
+            if y > 0 && !inside(x, y - 1) {
+                implies(&vertical_edges[(y - 1) * width + x], cell.top);
+            }
+        }
+    }
+    for variables in by_piece.iter().chain(&by_cell) {
+        exactly_one(&mut clauses, variables);
+    }
+
+    CnfEncoding { variable_count, clauses, placement_variables, grid }
+}
+
+fn new_connection_variables(variable_count: &mut usize) -> [i64; 3] {
+    CONNECTION_TYPES.map(|_| {
+        *variable_count += 1;
+        *variable_count as i64
+    })
+}
+
+fn at_most_one(clauses: &mut Vec<Vec<i64>>, variables: &[i64]) {
+    for (i, first) in variables.iter().enumerate() {
+        for second in &variables[i + 1..] {
+            clauses.push(vec![-first, -second]);
+        }
+    }
+}
+
+fn exactly_one(clauses: &mut Vec<Vec<i64>>, variables: &[i64]) {
+    clauses.push(variables.to_vec());
+    at_most_one(clauses, variables);
+}
+
+impl CnfEncoding {
+    pub fn to_dimacs(&self) -> String {
+        let mut dimacs = String::new();
+        writeln!(dimacs, "c {}x{} board, variables 1-{} are placements", self.grid.width, self.grid.height, self.placement_variables.len()).unwrap();
+        writeln!(dimacs, "p cnf {} {}", self.variable_count, self.clauses.len()).unwrap();
+        for clause in &self.clauses {
+            writeln!(dimacs, "{} 0", clause.iter().map(i64::to_string).collect::<Vec<_>>().join(" ")).unwrap();
+        }
+        dimacs
+    }
+
+    /// The model a SAT solver could have found for `solution`, in the competition format [`CnfEncoding::decode_model`] reads.
+    /// Fails if the solution's placements leave a clause unsatisfied, which would mean the encoding is wrong.
+    pub fn model_for(&self, solution: &Solution) -> Result<String, ModelError> {
+        let placement_table = self.grid.placement_table();
+        let mut values = vec![false; self.variable_count + 1];
+        for solver_move in &solution.placements {
+            let variable = (1..)
+                .zip(&self.placement_variables)
+                .find(|(_, index)| {
+                    let placement = &placement_table.placements[**index];
+                    placement.piece_id == solver_move.piece_id && placement.orientation == solver_move.orientation && placement.x == solver_move.x && placement.y == solver_move.y
+                })
+                .map(|(variable, _)| variable)
+                .ok_or_else(|| ModelError::Invalid(format!("Piece {} {:?} at ({}, {}) has no variable", solver_move.piece_id, solver_move.orientation, solver_move.x, solver_move.y)))?;
+            values[variable] = true;
+        }
+        // The placements decide every edge connection through their implications, edges nothing implies stay false
+        for clause in &self.clauses {
+            if let [placement, connection] = clause[..]
+                && placement < 0
+                && connection > 0
+                && values[placement.unsigned_abs() as usize]
+            {
+                values[connection as usize] = true;
+            }
+        }
+        let is_true = |literal: i64| values[literal.unsigned_abs() as usize] == (literal > 0);
+        if let Some(clause) = self.clauses.iter().position(|clause| !clause.iter().any(|literal| is_true(*literal))) {
+            return Err(ModelError::Invalid(format!("Clause {} isn't satisfied", clause + 1)));
+        }
+        let literals = (1..=self.variable_count).map(|variable| if values[variable] { variable as i64 } else { -(variable as i64) });
+        Ok(format!("s SATISFIABLE\nv {} 0\n", literals.map(|literal| literal.to_string()).collect::<Vec<_>>().join(" ")))
+    }
+
+    /// Reads a model as printed by a SAT solver, either in the competition format (`s SATISFIABLE`
+    /// followed by `v` lines) or as a plain list of literals, and places the pieces it describes.
+    pub fn decode_model(&self, model: &str) -> Result<Solution, ModelError> {
+        let mut true_variables = vec![];
+        for line in model.lines().map(str::trim) {
+            if line.starts_with('c') || line.is_empty() || line == "s SATISFIABLE" || line == "SAT" {
+                continue;
+            }
+            if line == "s UNSATISFIABLE" || line == "UNSAT" {
+                return Err(ModelError::Unsatisfiable);
+            }
+            for literal in line.strip_prefix('v').unwrap_or(line).split_whitespace() {
+                let literal = literal.parse::<i64>().map_err(|_| ModelError::Invalid(format!("`{}` isn't a literal", literal)))?;
+                if literal > 0 {
+                    true_variables.push(literal as usize);
+                }
+            }
+        }
+
+        let placement_table = self.grid.placement_table();
+        let mut grid = self.grid.snapshot();
+        let mut placements = vec![];
+        for variable in true_variables.into_iter().filter(|variable| *variable <= self.placement_variables.len()) {
+            let placement = &placement_table.placements[self.placement_variables[variable - 1]];
+            if !grid.pieces_left.iter().any(|piece| piece.piece_id() == placement.piece_id) {
+                return Err(ModelError::Invalid(format!("Variable {} places piece {} a second time", variable, placement.piece_id)));
+            }
+            grid.place_piece(placement_table.oriented_piece(placement), placement.x, placement.y).map_err(|err| ModelError::Invalid(format!("Variable {}: {}", variable, err)))?;
+            placements.push(SolverMove { piece_id: placement.piece_id, orientation: placement.orientation, x: placement.x, y: placement.y });
+        }
+        if !grid.is_solved() {
+            return Err(ModelError::Invalid("The placements don't solve the puzzle".to_owned()));
+        }
+        Ok(Solution { placements, grid, seed: None })
+    }
+}
@@ -0,0 +1,231 @@
+//! Runs the search on a worker thread for the viewer, so solving isn't capped at one step per frame
+//! and a slow step doesn't freeze the window. The worker sends snapshots of the grid back over a channel.
+
+use crate::constraint_solver::{Grid, SolveLimits, SolverState};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Takes a single step and stops auto mode
+    Step,
+    /// See [`SolverState::step_back`], stops auto mode
+    StepBack,
+    /// Steps back, or searches forward, until that many pieces are placed, stops auto mode.
+    /// Any command but a speed change cancels the search.
+    SeekDepth(usize),
+    ToggleAuto,
+    TogglePauseOnBacktrack,
+    Faster,
+    Slower,
+    ToggleUnbounded,
+}
+
+/// How many steps auto mode takes between two snapshots
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    StepsPerFrame(usize),
+    /// As many steps as fit in the time between two snapshots
+    Unbounded,
+}
+
+/// Why auto mode stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pause {
+    Backtracked,
+    Solved,
+    Finished,
+}
+
+#[derive(Debug)]
+pub struct Snapshot {
+    pub grid: Grid,
+    pub depth: usize,
+    pub steps: usize,
+    pub pause: Option<Pause>,
+}
+
+#[derive(Debug)]
+pub struct SolverThread {
+    commands: Option<Sender<Command>>,
+    snapshots: Receiver<Snapshot>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SolverThread {
+    /// Moves `solver` onto a new thread, auto mode sends at most one snapshot every `snapshot_interval`
+    pub fn spawn(solver: SolverState, snapshot_interval: Duration) -> SolverThread {
+        let (command_sender, commands) = mpsc::channel();
+        let (snapshot_sender, snapshots) = mpsc::channel();
+        let worker = Worker { solver, snapshots: snapshot_sender, snapshot_interval, auto_mode: false, seek_target: None, pause_on_backtrack: true, speed: Speed::StepsPerFrame(1) };
+        let handle = thread::spawn(move || worker.run(commands));
+        SolverThread { commands: Some(command_sender), snapshots, handle: Some(handle) }
+    }
+
+    pub fn send(&self, command: Command) {
+        // The worker only stops once the thread is dropped, so the channel is always open
+        self.commands.as_ref().unwrap().send(command).unwrap();
+    }
+
+    /// Waits up to `timeout` for a snapshot and returns the newest one, older ones are skipped
+    /// but a pause in any of them is kept
+    pub fn latest_snapshot(&self, timeout: Duration) -> Option<Snapshot> {
+        let mut latest = match self.snapshots.recv_timeout(timeout) {
+            Ok(snapshot) => snapshot,
+            Err(RecvTimeoutError::Timeout) => return None,
+            Err(RecvTimeoutError::Disconnected) => panic!("The solver thread stopped"),
+        };
+        for snapshot in self.snapshots.try_iter() {
+            let pause = latest.pause.or(snapshot.pause);
+            latest = Snapshot { pause, ..snapshot };
+        }
+        Some(latest)
+    }
+}
+
+impl Drop for SolverThread {
+    fn drop(&mut self) {
+        // Closing the command channel tells the worker to stop
+        self.commands = None;
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Worker {
+    solver: SolverState,
+    snapshots: Sender<Snapshot>,
+    snapshot_interval: Duration,
+    auto_mode: bool,
+    /// The depth a [`Command::SeekDepth`] is still searching for
+    seek_target: Option<usize>,
+    pause_on_backtrack: bool,
+    speed: Speed,
+}
+
+impl Worker {
+    fn run(mut self, commands: Receiver<Command>) {
+        self.send_snapshot(None);
+        loop {
+            // Wait for something to do while idle, otherwise only pick up the commands sent since the last frame
+            if !self.auto_mode && self.seek_target.is_none() {
+                match commands.recv() {
+                    Ok(command) => self.handle(command),
+                    Err(_) => return,
+                }
+            }
+            loop {
+                match commands.try_recv() {
+                    Ok(command) => self.handle(command),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+            if let Some(depth) = self.seek_target {
+                self.seek_frame(depth);
+            } else if self.auto_mode {
+                self.run_frame();
+            }
+        }
+    }
+
+    fn handle(&mut self, command: Command) {
+        if !matches!(command, Command::Faster | Command::Slower | Command::ToggleUnbounded) {
+            self.seek_target = None;
+        }
+        match command {
+            Command::Step => {
+                self.auto_mode = false;
+                let pause = self.step();
+                self.send_snapshot(pause);
+            }
+            Command::StepBack => {
+                self.auto_mode = false;
+                if !self.solver.step_back() {
+                    println!("Nothing to step back from");
+                }
+                self.send_snapshot(None);
+            }
+            Command::SeekDepth(depth) => {
+                self.auto_mode = false;
+                self.seek_target = Some(depth);
+            }
+            Command::ToggleAuto => self.auto_mode = !self.auto_mode,
+            Command::TogglePauseOnBacktrack => {
+                self.pause_on_backtrack = !self.pause_on_backtrack;
+                println!("Pause on backtrack: {}", self.pause_on_backtrack);
+            }
+            Command::Faster | Command::Slower | Command::ToggleUnbounded => {
+                self.speed = match (command, self.speed) {
+                    (Command::Faster, Speed::StepsPerFrame(steps)) => Speed::StepsPerFrame(steps.saturating_mul(2)),
+                    (Command::Slower, Speed::StepsPerFrame(steps)) => Speed::StepsPerFrame((steps / 2).max(1)),
+                    (Command::ToggleUnbounded, Speed::StepsPerFrame(_)) => Speed::Unbounded,
+                    (Command::ToggleUnbounded, Speed::Unbounded) => Speed::StepsPerFrame(1),
+                    (_, speed) => speed,
+                };
+                println!("Speed: {:?}", self.speed);
+            }
+        }
+    }
+
+    /// Takes one step of the search, returns why auto mode should pause after it, if it should
+    fn step(&mut self) -> Option<Pause> {
+        let backtracks = self.solver.stats().backtracks;
+        if self.solver.step_propagate().is_err() {
+            return Some(Pause::Finished);
+        }
+        if self.solver.current_grid().is_solved() {
+            Some(Pause::Solved)
+        } else if self.pause_on_backtrack && self.solver.stats().backtracks > backtracks {
+            Some(Pause::Backtracked)
+        } else {
+            None
+        }
+    }
+
+    /// Steps for one snapshot interval at the current speed, then sends a snapshot
+    fn run_frame(&mut self) {
+        let frame_end = Instant::now() + self.snapshot_interval;
+        let mut pause = None;
+        let mut steps = 0;
+        while pause.is_none() {
+            let frame_done = match self.speed {
+                Speed::StepsPerFrame(steps_per_frame) => steps >= steps_per_frame,
+                Speed::Unbounded => Instant::now() >= frame_end,
+            };
+            if frame_done {
+                break;
+            }
+            pause = self.step();
+            steps += 1;
+        }
+        if pause.is_some() {
+            self.auto_mode = false;
+        }
+        self.send_snapshot(pause);
+        thread::sleep(frame_end.saturating_duration_since(Instant::now()));
+    }
+
+    /// Moves towards `depth` for one snapshot interval, the search can take a long time to get deep,
+    /// so it is spread over frames to keep picking up commands in between
+    fn seek_frame(&mut self, depth: usize) {
+        match self.solver.seek_depth(depth, &SolveLimits::default().with_timeout(self.snapshot_interval)) {
+            Ok(true) => self.seek_target = None,
+            Ok(false) => {
+                println!("The search ended before reaching depth {}", depth);
+                self.seek_target = None;
+            }
+            Err(_) => {}
+        }
+        self.send_snapshot(None);
+    }
+
+    fn send_snapshot(&self, pause: Option<Pause>) {
+        let snapshot = Snapshot { grid: self.solver.current_grid().snapshot(), depth: self.solver.depth(), steps: self.solver.stats().steps, pause };
+        // The viewer is closing if nobody receives snapshots anymore, the worker stops with the command channel
+        let _ = self.snapshots.send(snapshot);
+    }
+}
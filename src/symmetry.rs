@@ -0,0 +1,146 @@
+use crate::constraint_solver::CellSolveState::Solved;
+use crate::constraint_solver::{Grid, PieceRotation, Solution};
+use crate::piece::Cell;
+use std::collections::{HashMap, HashSet};
+
+/// A transform of the whole board, mirroring (if any) happens before rotating
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoardSymmetry {
+    pub rotation: PieceRotation,
+    pub mirrored: bool,
+}
+
+impl BoardSymmetry {
+    /// The transforms that map a `width` x `height` board onto itself.
+    /// Only square boards can be rotated by 90 degrees.
+    pub fn for_board(width: usize, height: usize, include_reflections: bool) -> Vec<BoardSymmetry> {
+        let rotations: &[PieceRotation] = if width == height { &PieceRotation::ROTATIONS } else { &[PieceRotation::CCW0, PieceRotation::CCW180] };
+        let mirrors: &[bool] = if include_reflections { &[false, true] } else { &[false] };
+
+        mirrors.iter().flat_map(|&mirrored| rotations.iter().map(move |&rotation| BoardSymmetry { rotation, mirrored })).collect()
+    }
+
+    pub fn apply(&self, cells: &[Vec<Cell>]) -> Vec<Vec<Cell>> {
+        self.transform(cells, Cell::mirror_horizontal, Cell::rotate_90_ccw)
+    }
+
+    /// Moves every entry of `board` to where the transform takes its position, `mirror` and `rotate` turn the entries themselves
+    fn transform<T: Clone>(&self, board: &[Vec<T>], mirror: impl Fn(&T) -> T, rotate: impl Fn(&T) -> T) -> Vec<Vec<T>> {
+        let mut board = if self.mirrored { board.iter().map(|row| row.iter().rev().map(&mirror).collect()).collect() } else { board.to_vec() };
+        let quarter_turns = match self.rotation {
+            PieceRotation::CCW0 => 0,
+            PieceRotation::CCW90 => 1,
+            PieceRotation::CCW180 => 2,
+            PieceRotation::CCW270 => 3,
+        };
+        for _ in 0..quarter_turns {
+            board = rotate_90_ccw(&board, &rotate);
+        }
+        board
+    }
+}
+
+fn rotate_90_ccw<T>(board: &[Vec<T>], rotate: impl Fn(&T) -> T) -> Vec<Vec<T>> {
+    let height = board.len();
+    let width = board.first().map_or(0, Vec::len);
+    (0..width).map(|x| (0..height).map(|y| rotate(&board[y][width - x - 1])).collect()).collect()
+}
+
+/// The logical cells of a fully solved grid
+pub fn solved_cells(grid: &Grid) -> Vec<Vec<Cell>> {
+    grid.grid.iter().map(|row| row.iter().map(|cell| if let Solved(cell) = cell { *cell } else { panic!("Grid isn't fully solved") }).collect()).collect()
+}
+
+/// The id of the piece covering each cell of a solution
+pub fn piece_ids(solution: &Solution) -> Vec<Vec<usize>> {
+    let grid = &solution.grid;
+    let placement_table = grid.placement_table();
+    let mut piece_ids = vec![vec![0; grid.width]; grid.height];
+    for solver_move in &solution.placements {
+        let placement = placement_table
+            .covering(solver_move.x, solver_move.y)
+            .iter()
+            .map(|index| &placement_table.placements[*index])
+            .find(|placement| placement.piece_id == solver_move.piece_id && placement.orientation == solver_move.orientation && placement.x == solver_move.x && placement.y == solver_move.y)
+            .expect("Solution placement isn't on the board");
+        for (x, y, _) in &placement.cells {
+            piece_ids[*y][*x] = placement.piece_id;
+        }
+    }
+    piece_ids
+}
+
+/// The same representative for every solution that is a transformed copy of `solution`, so two solutions are
+/// equivalent exactly when their canonical forms are equal. The form pairs the piece covering each cell with the
+/// cell itself, so solutions with the same connections but differently assigned pieces stay apart.
+pub fn canonical_solution(solution: &Solution, symmetries: &[BoardSymmetry]) -> Vec<Vec<(usize, Cell)>> {
+    let board = solution_board(solution);
+    transformed_boards(&board, symmetries).min().unwrap_or(board)
+}
+
+fn solution_board(solution: &Solution) -> Vec<Vec<(usize, Cell)>> {
+    piece_ids(solution).into_iter().zip(solved_cells(&solution.grid)).map(|(ids, cells)| ids.into_iter().zip(cells).collect()).collect()
+}
+
+fn transformed_boards<'a>(board: &'a [Vec<(usize, Cell)>], symmetries: &'a [BoardSymmetry]) -> impl Iterator<Item = Vec<Vec<(usize, Cell)>>> + 'a {
+    symmetries.iter().map(move |symmetry| symmetry.transform(board, |(id, cell)| (*id, cell.mirror_horizontal()), |(id, cell)| (*id, cell.rotate_90_ccw())))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolutionCounts {
+    /// Solutions including copies that only differ by a board symmetry, also the rotated copies a search
+    /// with broken symmetry skipped
+    pub raw: usize,
+    /// Solutions that remain once symmetric copies are merged
+    pub unique: usize,
+}
+
+/// Adds up the solutions of a search into [`SolutionCounts`], tallies of searches over parts of the tree can be merged.
+///
+/// A search with [broken symmetry](crate::constraint_solver::SolverState::break_symmetry) skips most rotated copies
+/// of a solution, so the tally remembers every rotation class it saw and counts all of its distinct rotated copies.
+#[derive(Debug)]
+pub struct SolutionTally {
+    symmetries: Vec<BoardSymmetry>,
+    rotations: Vec<BoardSymmetry>,
+    broken_symmetry: bool,
+    found: usize,
+    /// The number of distinct rotated copies of each rotation class, only kept when symmetry is broken
+    rotated_copies: HashMap<Vec<Vec<(usize, Cell)>>, usize>,
+    unique: HashSet<Vec<Vec<(usize, Cell)>>>,
+}
+
+impl SolutionTally {
+    pub fn new(width: usize, height: usize, include_reflections: bool, broken_symmetry: bool) -> SolutionTally {
+        SolutionTally {
+            symmetries: BoardSymmetry::for_board(width, height, include_reflections),
+            rotations: BoardSymmetry::for_board(width, height, false),
+            broken_symmetry,
+            found: 0,
+            rotated_copies: HashMap::new(),
+            unique: HashSet::new(),
+        }
+    }
+
+    pub fn add(&mut self, solution: &Solution) {
+        let board = solution_board(solution);
+        self.found += 1;
+        if self.broken_symmetry {
+            let rotated_copies = transformed_boards(&board, &self.rotations).collect::<HashSet<_>>();
+            let rotation_class = rotated_copies.iter().min().unwrap().clone();
+            self.rotated_copies.insert(rotation_class, rotated_copies.len());
+        }
+        self.unique.insert(transformed_boards(&board, &self.symmetries).min().unwrap_or(board));
+    }
+
+    pub fn merge(&mut self, other: SolutionTally) {
+        self.found += other.found;
+        self.rotated_copies.extend(other.rotated_copies);
+        self.unique.extend(other.unique);
+    }
+
+    pub fn counts(&self) -> SolutionCounts {
+        let raw = if self.broken_symmetry { self.rotated_copies.values().sum() } else { self.found };
+        SolutionCounts { raw, unique: self.unique.len() }
+    }
+}
@@ -0,0 +1,345 @@
+//! Recording every decision the search makes into a trace, and replaying a trace to get the grid at any step,
+//! so a long run can be debugged offline or attached to a bug report.
+//!
+//! Traces are saved as text, one line per event after a short header:
+//!
+//! ```text
+//! trace 6x6
+//! pieces 1 2 3 4 5 6 7 8 9 10 12 13 14
+//! select 0 0
+//! place 1 CCW0 0 0
+//! reject 5 CCW270 1 1 dead-end
+//! back 2
+//! solved
+//! ```
+//!
+//! Pieces are only stored by id, so a trace has to be replayed with the piece set it was recorded with.
+
+use crate::constraint_solver::{Grid, PieceOrientation, Solution, SolverMove, SolverState};
+use crate::observer::{Rejection, SolverObserver};
+use crate::piece::PieceOps;
+use std::fmt::{Display, Formatter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+    CellSelected { x: usize, y: usize },
+    Placed(SolverMove),
+    Rejected(SolverMove, Rejection),
+    /// The search went back up to `depth`
+    Backtracked { depth: usize },
+    SolutionFound,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub width: usize,
+    pub height: usize,
+    /// The pieces the search started with
+    pub piece_ids: Vec<usize>,
+    pub events: Vec<TraceEvent>,
+}
+
+#[derive(Debug)]
+pub struct TraceError {
+    /// 1-based line the error was found on, 0 if it isn't tied to a line
+    pub line: usize,
+    pub message: String,
+}
+
+impl TraceError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        TraceError { line, message: message.into() }
+    }
+}
+
+impl Display for TraceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.line == 0 { write!(f, "{}", self.message) } else { write!(f, "line {}: {}", self.line, self.message) }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl Trace {
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        writeln!(text, "trace {}x{}", self.width, self.height).unwrap();
+        writeln!(text, "pieces {}", self.piece_ids.iter().map(usize::to_string).collect::<Vec<_>>().join(" ")).unwrap();
+        let write_move = |text: &mut String, solver_move: &SolverMove| write!(text, "{} {:?} {} {}", solver_move.piece_id, solver_move.orientation, solver_move.x, solver_move.y).unwrap();
+        for event in &self.events {
+            match event {
+                TraceEvent::CellSelected { x, y } => write!(text, "select {} {}", x, y).unwrap(),
+                TraceEvent::Placed(solver_move) => {
+                    text.push_str("place ");
+                    write_move(&mut text, solver_move);
+                }
+                TraceEvent::Rejected(solver_move, rejection) => {
+                    text.push_str("reject ");
+                    write_move(&mut text, solver_move);
+                    text.push_str(match rejection {
+                        Rejection::DoesNotFit => " does-not-fit",
+                        Rejection::DeadEnd => " dead-end",
+                    });
+                }
+                TraceEvent::Backtracked { depth } => write!(text, "back {}", depth).unwrap(),
+                TraceEvent::SolutionFound => text.push_str("solved"),
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Trace, TraceError> {
+        let source = std::fs::read_to_string(path.as_ref()).map_err(|err| TraceError::new(0, format!("Failed to read {}: {}", path.as_ref().display(), err)))?;
+        Trace::parse(&source)
+    }
+
+    pub fn parse(source: &str) -> Result<Trace, TraceError> {
+        let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line.trim())).filter(|(_, line)| !line.is_empty());
+
+        let (line_number, header) = lines.next().ok_or_else(|| TraceError::new(0, "Trace is empty"))?;
+        let (width, height) = header.strip_prefix("trace ").and_then(|size| size.split_once('x')).ok_or_else(|| TraceError::new(line_number, format!("Expected `trace <width>x<height>`, found `{}`", header)))?;
+        let width = parse_number(line_number, width)?;
+        let height = parse_number(line_number, height)?;
+
+        let (line_number, pieces) = lines.next().ok_or_else(|| TraceError::new(0, "Trace is missing its pieces"))?;
+        let pieces = pieces.strip_prefix("pieces").ok_or_else(|| TraceError::new(line_number, format!("Expected `pieces <id>...`, found `{}`", pieces)))?;
+        let piece_ids = pieces.split_whitespace().map(|id| parse_number(line_number, id)).collect::<Result<Vec<_>, _>>()?;
+
+        let events = lines.map(|(line_number, line)| parse_event(line_number, line)).collect::<Result<Vec<_>, _>>()?;
+        Ok(Trace { width, height, piece_ids, events })
+    }
+}
+
+fn parse_number(line_number: usize, number: &str) -> Result<usize, TraceError> {
+    number.parse().map_err(|_| TraceError::new(line_number, format!("`{}` isn't a number", number)))
+}
+
+fn parse_move(line_number: usize, parts: &[&str]) -> Result<SolverMove, TraceError> {
+    let [piece_id, orientation, x, y] = parts else {
+        return Err(TraceError::new(line_number, "Expected a move as `<piece id> <orientation> <x> <y>`"));
+    };
+    let orientation = PieceOrientation::ORIENTATIONS.into_iter().find(|known| format!("{:?}", known) == *orientation).ok_or_else(|| TraceError::new(line_number, format!("Unknown orientation `{}`", orientation)))?;
+    Ok(SolverMove { piece_id: parse_number(line_number, piece_id)?, orientation, x: parse_number(line_number, x)?, y: parse_number(line_number, y)? })
+}
+
+fn parse_event(line_number: usize, line: &str) -> Result<TraceEvent, TraceError> {
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    Ok(match parts.as_slice() {
+        ["select", x, y] => TraceEvent::CellSelected { x: parse_number(line_number, x)?, y: parse_number(line_number, y)? },
+        ["place", solver_move @ ..] => TraceEvent::Placed(parse_move(line_number, solver_move)?),
+        ["reject", solver_move @ .., rejection] => {
+            let rejection = match *rejection {
+                "does-not-fit" => Rejection::DoesNotFit,
+                "dead-end" => Rejection::DeadEnd,
+                _ => return Err(TraceError::new(line_number, format!("Unknown rejection `{}`, expected `does-not-fit` or `dead-end`", rejection))),
+            };
+            TraceEvent::Rejected(parse_move(line_number, solver_move)?, rejection)
+        }
+        ["back", depth] => TraceEvent::Backtracked { depth: parse_number(line_number, depth)? },
+        ["solved"] => TraceEvent::SolutionFound,
+        _ => return Err(TraceError::new(line_number, format!("Unknown event `{}`", line))),
+    })
+}
+
+/// Records the events of a search into a [`Trace`]. Clones share the same trace, so keep one
+/// around to read the trace after handing the other to [`SolverState::add_observer`].
+/// Create it before the search places anything, placements made earlier aren't in the trace.
+#[derive(Debug, Clone)]
+pub struct TraceRecorder {
+    trace: Arc<Mutex<Trace>>,
+}
+
+impl TraceRecorder {
+    pub fn for_solver(solver: &SolverState) -> TraceRecorder {
+        let grid = solver.current_grid();
+        let trace = Trace { width: grid.width, height: grid.height, piece_ids: solver.pieces().iter().map(|piece| piece.piece_id()).collect(), events: vec![] };
+        TraceRecorder { trace: Arc::new(Mutex::new(trace)) }
+    }
+
+    /// A copy of everything recorded so far
+    pub fn trace(&self) -> Trace {
+        self.trace.lock().unwrap().clone()
+    }
+
+    fn record(&self, event: TraceEvent) {
+        self.trace.lock().unwrap().events.push(event);
+    }
+}
+
+impl SolverObserver for TraceRecorder {
+    fn cell_selected(&mut self, _grid: &Grid, x: usize, y: usize) {
+        self.record(TraceEvent::CellSelected { x, y });
+    }
+
+    fn piece_placed(&mut self, _grid: &Grid, solver_move: &SolverMove, _depth: usize) {
+        self.record(TraceEvent::Placed(solver_move.clone()));
+    }
+
+    fn piece_rejected(&mut self, _grid: &Grid, solver_move: &SolverMove, rejection: Rejection) {
+        self.record(TraceEvent::Rejected(solver_move.clone(), rejection));
+    }
+
+    fn backtracked(&mut self, _grid: &Grid, depth: usize) {
+        self.record(TraceEvent::Backtracked { depth });
+    }
+
+    fn solution_found(&mut self, _solution: &Solution) {
+        self.record(TraceEvent::SolutionFound);
+    }
+}
+
+/// Steps through a trace, rebuilding the grid the search had after each event
+#[derive(Debug)]
+pub struct TraceReplayer {
+    trace: Trace,
+    pieces: Vec<&'static dyn PieceOps>,
+    grid: Grid,
+    /// The grid's checkpoint from before each placement on the current path
+    checkpoints: Vec<usize>,
+    /// How many events have been applied to the grid
+    position: usize,
+}
+
+impl TraceReplayer {
+    /// `pieces` has to contain every piece the trace was recorded with
+    pub fn new(trace: Trace, pieces: &[&'static dyn PieceOps]) -> Result<TraceReplayer, TraceError> {
+        let pieces = trace
+            .piece_ids
+            .iter()
+            .map(|id| pieces.iter().find(|piece| piece.piece_id() == *id).copied().ok_or_else(|| TraceError::new(0, format!("The trace uses piece {} which isn't in the piece set", id))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let grid = Grid::with_pieces(trace.width, trace.height, &pieces);
+        Ok(TraceReplayer { trace, pieces, grid, checkpoints: vec![], position: 0 })
+    }
+
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// The grid after the first `position` events
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.trace.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trace.events.is_empty()
+    }
+
+    /// Applies the next event, returns false at the end of the trace
+    pub fn step_forward(&mut self) -> Result<bool, TraceError> {
+        let Some(event) = self.trace.events.get(self.position) else {
+            return Ok(false);
+        };
+        match event {
+            TraceEvent::Placed(solver_move) => {
+                let piece = self.pieces.iter().find(|piece| piece.piece_id() == solver_move.piece_id).ok_or_else(|| TraceError::new(0, format!("Event {} places unknown piece {}", self.position, solver_move.piece_id)))?;
+                if !self.grid.pieces_left.iter().any(|left| left.piece_id() == solver_move.piece_id) {
+                    return Err(TraceError::new(0, format!("Event {} places piece {} a second time", self.position, solver_move.piece_id)));
+                }
+                let oriented = piece.orient(solver_move.orientation);
+                // Also checks the move stays on the board
+                if !self.grid.can_place_piece(&*oriented, solver_move.x, solver_move.y) {
+                    return Err(TraceError::new(0, format!("Event {} places piece {} at ({}, {}) where it doesn't fit", self.position, solver_move.piece_id, solver_move.x, solver_move.y)));
+                }
+                let checkpoint = self.grid.checkpoint();
+                // The search only reports placements that propagated, anything else means the trace doesn't belong to these pieces
+                if !self.grid.place_piece_unchecked(&*oriented, solver_move.x, solver_move.y) {
+                    self.grid.undo_to(checkpoint);
+                    return Err(TraceError::new(0, format!("Event {} places piece {} where propagation fails", self.position, solver_move.piece_id)));
+                }
+                self.checkpoints.push(checkpoint);
+            }
+            TraceEvent::Backtracked { depth } => {
+                let checkpoint = *self.checkpoints.get(*depth).ok_or_else(|| TraceError::new(0, format!("Event {} backtracks to depth {} from depth {}", self.position, depth, self.checkpoints.len())))?;
+                self.grid.undo_to(checkpoint);
+                self.checkpoints.truncate(*depth);
+            }
+            TraceEvent::CellSelected { .. } | TraceEvent::Rejected(..) | TraceEvent::SolutionFound => {}
+        }
+        self.position += 1;
+        Ok(true)
+    }
+
+    /// Moves to the grid after the first `position` events, rewinding to the start if it lies behind
+    pub fn seek(&mut self, position: usize) -> Result<&Grid, TraceError> {
+        if position < self.position {
+            if let Some(&checkpoint) = self.checkpoints.first() {
+                self.grid.undo_to(checkpoint);
+            }
+            self.checkpoints.clear();
+            self.position = 0;
+        }
+        while self.position < position && self.step_forward()? {}
+        Ok(&self.grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_piece_domain;
+
+    fn recorded_solve() -> Trace {
+        let mut solver = SolverState::new(6, 6);
+        let recorder = TraceRecorder::for_solver(&solver);
+        solver.add_observer(Box::new(recorder.clone()));
+        solver.solve().unwrap();
+        recorder.trace()
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let trace = recorded_solve();
+        assert!(trace.events.iter().any(|event| matches!(event, TraceEvent::Backtracked { .. })));
+        assert_eq!(Trace::parse(&trace.to_text()).unwrap(), trace);
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_event() {
+        let err = Trace::parse("trace 6x6\npieces 1 2\n\nselect 0 0\nplace 1 CCW45 0 0\n").unwrap_err();
+        assert_eq!(err.line, 5);
+        let err = Trace::parse("trace 6 6\n").unwrap_err();
+        assert_eq!(err.line, 1);
+    }
+
+    #[test]
+    fn replays_to_the_solution() {
+        let trace = recorded_solve();
+        let mut replayer = TraceReplayer::new(trace, get_piece_domain()).unwrap();
+        assert!(replayer.seek(replayer.len()).unwrap().is_solved());
+        assert!(!replayer.seek(0).unwrap().is_solved());
+    }
+
+    #[test]
+    fn refuses_placements_the_search_couldnt_have_made() {
+        let mut trace = recorded_solve();
+        let first_placement = trace.events.iter().position(|event| matches!(event, TraceEvent::Placed(_))).unwrap();
+        let TraceEvent::Placed(solver_move) = trace.events[first_placement].clone() else { unreachable!() };
+        trace.events.truncate(first_placement + 1);
+
+        let mut repeated = trace.clone();
+        repeated.events.push(TraceEvent::Placed(solver_move.clone()));
+        let mut off_board = trace.clone();
+        off_board.events[first_placement] = TraceEvent::Placed(SolverMove { x: 6, ..solver_move });
+
+        for trace in [repeated, off_board] {
+            let mut replayer = TraceReplayer::new(trace, get_piece_domain()).unwrap();
+            assert!(replayer.seek(replayer.len()).is_err());
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Cheap feasibility checks on a board and piece set, run before searching so impossible
+//! puzzles are refused straight away instead of exhausting the whole search space.
+
+use crate::constraint_solver::SolveError;
+use crate::piece::ConnectionType::{Double, NoConnection, Straight};
+use crate::piece::{ConnectionType, PieceOps};
+use std::collections::HashSet;
+
+/// [`validate_puzzle`] for the solvers, which refuse to search when any problem is found
+pub fn check_puzzle(width: usize, height: usize, pieces: &[&dyn PieceOps]) -> Result<(), SolveError> {
+    let diagnostics = validate_puzzle(width, height, pieces);
+    if diagnostics.is_empty() { Ok(()) } else { Err(SolveError::InvalidInput(diagnostics)) }
+}
+
+/// Returns a human-readable description of every problem found, an empty list means no problems were found.
+/// Passing doesn't guarantee a solution exists, failing guarantees it doesn't.
+pub fn validate_puzzle(width: usize, height: usize, pieces: &[&dyn PieceOps]) -> Vec<String> {
+    let mut diagnostics = vec![];
+
+    if width == 0 || height == 0 {
+        diagnostics.push(format!("Board {}x{} has no cells", width, height));
+        return diagnostics;
+    }
+
+    let mut seen_ids = HashSet::new();
+    for piece in pieces {
+        if !seen_ids.insert(piece.piece_id()) {
+            diagnostics.push(format!("Piece id {} is used by more than one piece", piece.piece_id()));
+        }
+    }
+
+    let piece_area: usize = pieces.iter().map(|piece| piece.width() * piece.height()).sum();
+    if piece_area != width * height {
+        diagnostics.push(format!("Pieces cover {} cells but the {}x{} board has {}", piece_area, width, height, width * height));
+    }
+
+    for piece in pieces {
+        let (short_side, long_side) = (piece.width().min(piece.height()), piece.width().max(piece.height()));
+        if short_side > width.min(height) || long_side > width.max(height) {
+            diagnostics.push(format!("Piece {} ({}x{}) doesn't fit on the {}x{} board in any rotation", piece.piece_id(), piece.width(), piece.height(), width, height));
+        }
+        diagnostics.extend(check_internal_edges(*piece));
+    }
+
+    let boundary_edges: Vec<Vec<ConnectionType>> = pieces.iter().map(|piece| boundary_edges(*piece)).collect();
+    let count_edges = |edges: &[ConnectionType], connection: ConnectionType| edges.iter().filter(|edge| **edge == connection).count();
+
+    // Each connection on a piece's outline has to meet the same connection on another piece
+    for connection in [Straight, Double] {
+        let total: usize = boundary_edges.iter().map(|edges| count_edges(edges, connection)).sum();
+        for (piece, edges) in pieces.iter().zip(&boundary_edges) {
+            let own = count_edges(edges, connection);
+            if own > total - own {
+                diagnostics.push(format!("Piece {} has {} {:?} edges but the other pieces only have {} to connect to", piece.piece_id(), own, connection, total - own));
+            }
+        }
+        if !total.is_multiple_of(2) {
+            diagnostics.push(format!("There is an odd number ({}) of {:?} edges, one of them can never be connected", total, connection));
+        }
+    }
+
+    // The board's border can only be lined with edges that don't connect to anything
+    let perimeter = 2 * (width + height);
+    let no_connection_edges: usize = boundary_edges.iter().map(|edges| count_edges(edges, NoConnection)).sum();
+    if no_connection_edges < perimeter {
+        diagnostics.push(format!("Only {} piece edges have no connection but the board's border needs {}", no_connection_edges, perimeter));
+    } else if !(no_connection_edges - perimeter).is_multiple_of(2) {
+        diagnostics.push(format!("{} piece edges have no connection, after lining the {} long border an odd number are left which can't be paired up", no_connection_edges, perimeter));
+    }
+
+    diagnostics
+}
+
+/// Every edge on the outline of the piece
+fn boundary_edges(piece: &dyn PieceOps) -> Vec<ConnectionType> {
+    let (width, height) = (piece.width(), piece.height());
+    let cells = piece.cells_flat();
+    let mut edges = vec![];
+    for x in 0..width {
+        edges.push(cells[x].top);
+        edges.push(cells[(height - 1) * width + x].bottom);
+    }
+    for y in 0..height {
+        edges.push(cells[y * width].left);
+        edges.push(cells[y * width + width - 1].right);
+    }
+    edges
+}
+
+/// Cells inside a piece can never be separated, so the edges between them have to match
+fn check_internal_edges(piece: &dyn PieceOps) -> Vec<String> {
+    let (width, height) = (piece.width(), piece.height());
+    let cells = piece.cells_flat();
+    let mut diagnostics = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            let cell = cells[y * width + x];
+            if x + 1 < width && cell.right != cells[y * width + x + 1].left {
+                diagnostics.push(format!("Piece {} has mismatched edges between its cells ({}, {}) and ({}, {})", piece.piece_id(), x, y, x + 1, y));
+            }
+            if y + 1 < height && cell.bottom != cells[(y + 1) * width + x].top {
+                diagnostics.push(format!("Piece {} has mismatched edges between its cells ({}, {}) and ({}, {})", piece.piece_id(), x, y, x, y + 1));
+            }
+        }
+    }
+    diagnostics
+}